@@ -14,82 +14,224 @@
 
 #![warn(clippy::all)]
 
-use anyhow::Result;
-use itertools::Itertools;
-use std::collections::HashSet;
+use anyhow::{anyhow, Result};
+use std::ops::{BitAnd, BitOr};
 use std::time::Instant;
+use thiserror::Error;
 
-fn split_rucksack(s: &str) -> (String, String) {
-    let s1 = s[0..s.len() / 2].to_owned();
-    let s2 = s[(s.len() / 2)..].to_owned();
-    (s1, s2)
+#[derive(Debug, Error, PartialEq, Eq)]
+enum ParseError {
+    #[error("line {line}: odd number of items ({len})")]
+    OddLength { line: usize, len: usize },
+    #[error("line {line}: `{item}` is not an item")]
+    BadItem { line: usize, item: char },
+    #[error("line {line}: group of {found}, expected {size}")]
+    IncompleteGroup {
+        line: usize,
+        size: usize,
+        found: usize,
+    },
+    #[error("groups must hold at least one rucksack")]
+    EmptyGroup,
 }
 
-fn common_item(s1: &str, s2: &str) -> char {
-    let h1: HashSet<char> = s1.chars().collect();
-    let h2: HashSet<char> = s2.chars().collect();
-    *(h1.intersection(&h2).into_iter().next().unwrap())
+/// Elves per badge group in the puzzle.
+const GROUP_SIZE: usize = 3;
+
+/// A set of rucksack items, one bit per priority.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ItemSet(u64);
+
+impl ItemSet {
+    const EMPTY: ItemSet = ItemSet(0);
+
+    fn insert(&mut self, ch: char) {
+        if let Some(p) = item_priority(ch) {
+            self.0 |= 1 << (p - 1);
+        }
+    }
+
+    fn contains(&self, ch: char) -> bool {
+        item_priority(ch).is_some_and(|p| self.0 & (1 << (p - 1)) != 0)
+    }
+
+    /// Items in priority order.
+    fn iter(&self) -> impl Iterator<Item = char> + '_ {
+        ('a'..='z').chain('A'..='Z').filter(|&ch| self.contains(ch))
+    }
+}
+
+impl FromIterator<char> for ItemSet {
+    fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> Self {
+        let mut set = ItemSet::EMPTY;
+        for ch in iter {
+            set.insert(ch);
+        }
+        set
+    }
+}
+
+impl BitAnd for ItemSet {
+    type Output = ItemSet;
+
+    fn bitand(self, rhs: ItemSet) -> ItemSet {
+        ItemSet(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for ItemSet {
+    type Output = ItemSet;
+
+    fn bitor(self, rhs: ItemSet) -> ItemSet {
+        ItemSet(self.0 | rhs.0)
+    }
+}
+
+fn split_rucksack(s: &str) -> (&str, &str) {
+    s.split_at(s.len() / 2)
+}
+
+/// Items carried by every one of the rucksacks.
+fn common_items(rucksacks: &[&str]) -> ItemSet {
+    rucksacks
+        .iter()
+        .map(|r| r.chars().collect::<ItemSet>())
+        .reduce(BitAnd::bitand)
+        .unwrap_or_default()
+}
+
+/// Items carried by any of the rucksacks.
+fn all_items(rucksacks: &[&str]) -> ItemSet {
+    rucksacks
+        .iter()
+        .map(|r| r.chars().collect::<ItemSet>())
+        .fold(ItemSet::EMPTY, BitOr::bitor)
 }
 
-fn common_item_among_three(s1: &str, s2: &str, s3: &str) -> char {
-    let h1: HashSet<char> = s1.chars().collect();
-    let h2: HashSet<char> = s2.chars().collect();
-    // TODO: Horrible hack. There must be a way to collect directly into a HashSet.
-    let s1_2: String = h1.intersection(&h2).collect();
-    let h1_2: HashSet<char> = s1_2.chars().collect();
-    let h3: HashSet<char> = s3.chars().collect();
-    *(h1_2.intersection(&h3).into_iter().next().unwrap())
+fn common_item(rucksacks: &[&str]) -> Option<char> {
+    common_items(rucksacks).iter().next()
 }
 
-fn item_priority(ch: char) -> i32 {
+fn item_priority(ch: char) -> Option<i32> {
     let v = match ch {
         'a'..='z' => u32::from(ch) - u32::from('a') + 1,
         'A'..='Z' => u32::from(ch) - u32::from('A') + 27,
-        _ => 0,
+        _ => return None,
     };
-    v.try_into().unwrap()
+    v.try_into().ok()
 }
 
-fn parse1(input: &str) -> Vec<(String, String)> {
-    input.lines().map(split_rucksack).collect()
+/// The rucksacks, checked to hold only letters.
+fn rucksacks(input: &str) -> Result<Vec<&str>, ParseError> {
+    input
+        .lines()
+        .enumerate()
+        .map(
+            |(idx, text)| match text.chars().find(|&ch| item_priority(ch).is_none()) {
+                Some(item) => Err(ParseError::BadItem {
+                    line: idx + 1,
+                    item,
+                }),
+                None => Ok(text),
+            },
+        )
+        .collect()
 }
 
-fn part1(vals: &[(String, String)]) -> i32 {
+fn parse1(input: &str) -> Result<Vec<(&str, &str)>, ParseError> {
+    rucksacks(input)?
+        .into_iter()
+        .enumerate()
+        .map(|(idx, text)| {
+            if text.len() % 2 == 0 {
+                Ok(split_rucksack(text))
+            } else {
+                Err(ParseError::OddLength {
+                    line: idx + 1,
+                    len: text.len(),
+                })
+            }
+        })
+        .collect()
+}
+
+/// Sum of priorities; an error names the first rucksack whose compartments
+/// share nothing.
+fn part1(vals: &[(&str, &str)]) -> Result<i32> {
     vals.iter()
-        .map(|(s1, s2)| item_priority(common_item(s1, s2)))
+        .enumerate()
+        .map(|(idx, &(s1, s2))| {
+            common_item(&[s1, s2])
+                .and_then(item_priority)
+                .ok_or_else(|| anyhow!("rucksack {} has no item in both compartments", idx + 1))
+        })
         .sum()
 }
 
-fn parse2(input: &str) -> Vec<(String, String, String)> {
-    let mut result: Vec<(String, String, String)> = vec![];
-    for chunk in &input.lines().chunks(3) {
-        let (x, y, z) = chunk.collect_tuple().unwrap();
-        result.push((x.to_owned(), y.to_owned(), z.to_owned()));
+/// The rucksacks, checked to split evenly into groups of `size`.
+fn parse2(input: &str, size: usize) -> Result<Vec<&str>, ParseError> {
+    if size == 0 {
+        return Err(ParseError::EmptyGroup);
+    }
+    let sacks = rucksacks(input)?;
+    let found = sacks.len() % size;
+    if found != 0 {
+        return Err(ParseError::IncompleteGroup {
+            line: sacks.len() - found + 1,
+            size,
+            found,
+        });
     }
-    result
+    Ok(sacks)
 }
 
-fn part2(vals: &[(String, String, String)]) -> i32 {
-    vals.iter()
-        .map(|(s1, s2, s3)| item_priority(common_item_among_three(s1, s2, s3)))
+/// Sum of badge priorities; an error names the first group with no badge.
+fn part2(sacks: &[&str], size: usize) -> Result<i32> {
+    sacks
+        .chunks_exact(size)
+        .enumerate()
+        .map(|(idx, group)| {
+            common_item(group).and_then(item_priority).ok_or_else(|| {
+                anyhow!(
+                    "group {} (lines {}-{}) has no common item",
+                    idx + 1,
+                    idx * size + 1,
+                    (idx + 1) * size
+                )
+            })
+        })
         .sum()
 }
 
 fn main() -> Result<()> {
     let input = include_str!("../../input/03.txt");
+    let args: Vec<String> = std::env::args().collect();
+    let size = match args.iter().skip_while(|a| *a != "--group").nth(1) {
+        Some(n) => n.parse()?,
+        None => GROUP_SIZE,
+    };
 
-    let parsed = parse1(input);
+    let parsed = parse1(input)?;
 
     let timer = Instant::now();
-    let p1 = part1(&parsed);
+    let p1 = part1(&parsed)?;
     println!("Part 1: {}\n(elapsed: {:.2?})", p1, timer.elapsed());
 
-    let parsed = parse2(input);
+    let parsed = parse2(input, size)?;
 
     let timer = Instant::now();
-    let p2 = part2(&parsed);
+    let p2 = part2(&parsed, size)?;
     println!("Part 2: {}\n(elapsed: {:.2?})", p2, timer.elapsed());
 
+    if args.iter().any(|a| a == "--missing") {
+        let carried = all_items(&parsed);
+        let missing: String = ('a'..='z')
+            .chain('A'..='Z')
+            .filter(|ch| !carried.contains(*ch))
+            .collect();
+        println!("Carried by nobody: {}", missing);
+    }
+
     Ok(())
 }
 
@@ -104,13 +246,97 @@ CrZsJsPPZsGzwwsLwLmpwMDw"#;
 
     #[test]
     fn part1_works() {
-        let input = super::parse1(SAMPLE);
-        assert_eq!(157, super::part1(&input));
+        let input = super::parse1(SAMPLE).unwrap();
+        assert_eq!(157, super::part1(&input).unwrap());
     }
 
     #[test]
     fn part2_works() {
-        let input = super::parse2(SAMPLE);
-        assert_eq!(70, super::part2(&input));
+        let input = super::parse2(SAMPLE, super::GROUP_SIZE).unwrap();
+        assert_eq!(70, super::part2(&input, super::GROUP_SIZE).unwrap());
+    }
+
+    #[test]
+    fn item_sets_combine() {
+        use super::ItemSet;
+        let a: ItemSet = "abcZ".chars().collect();
+        let b: ItemSet = "bcdZ".chars().collect();
+        assert_eq!("bcZ", (a & b).iter().collect::<String>());
+        assert_eq!(5, (a | b).iter().count());
+        assert!((a & b).contains('Z'));
+        assert!(!(a & b).contains('a'));
+        assert_eq!(None, ItemSet::EMPTY.iter().next());
+        assert_eq!(
+            "abcdZ",
+            super::all_items(&["abZ", "bcZ", "d"])
+                .iter()
+                .collect::<String>()
+        );
+        assert_eq!(
+            "bZ",
+            super::common_items(&["abZ", "bcZ", "bZZ"])
+                .iter()
+                .collect::<String>()
+        );
+    }
+
+    #[test]
+    fn common_item_is_optional() {
+        assert_eq!(
+            Some('p'),
+            super::common_item(&["vJrwpWtwJgWr", "hcsFMMfFFhFp"])
+        );
+        assert_eq!(None, super::common_item(&["abc", "def"]));
+        assert_eq!(
+            "rucksack 2 has no item in both compartments",
+            super::part1(&[("ab", "ba"), ("abc", "def")])
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "group 2 (lines 3-4) has no common item",
+            super::part2(&["ab", "bc", "ab", "cd"], 2)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn groups_can_be_any_size() {
+        let input = super::parse2(SAMPLE, 2).unwrap();
+        assert_eq!(6, input.len());
+        assert_eq!(
+            super::common_item(&input[..2]),
+            super::common_item(&[
+                "vJrwpWtwJgWrhcsFMMfFFhFp",
+                "jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL"
+            ])
+        );
+        assert_eq!(
+            Err(super::ParseError::IncompleteGroup {
+                line: 5,
+                size: 4,
+                found: 2
+            }),
+            super::parse2(SAMPLE, 4)
+        );
+        assert_eq!(Err(super::ParseError::EmptyGroup), super::parse2(SAMPLE, 0));
+    }
+
+    #[test]
+    fn bad_rucksacks_are_reported() {
+        use super::ParseError;
+        assert_eq!(
+            Err(ParseError::OddLength { line: 2, len: 5 }),
+            super::parse1("abab\nabcab")
+        );
+        assert_eq!(
+            Err(ParseError::BadItem { line: 1, item: '1' }),
+            super::parse1("ab1b")
+        );
+        assert_eq!(
+            Err(ParseError::BadItem { line: 2, item: ' ' }),
+            super::parse2("ab\nc d\nef", 3)
+        );
     }
 }
@@ -14,70 +14,204 @@
 
 #![warn(clippy::all)]
 
+use anyhow::Result;
+use aoc22::interval::{Interval, IntervalSet};
+use nom::{
+    character::complete::{char, digit1},
+    combinator::{all_consuming, map_res},
+    sequence::separated_pair,
+    IResult,
+};
 use std::time::Instant;
+use thiserror::Error;
 
-struct Assignment {
-    low: i32,
-    high: i32,
+#[derive(Debug, Error, PartialEq, Eq)]
+enum ParseError {
+    #[error("line {line}: malformed pair `{text}`")]
+    Malformed { line: usize, text: String },
+    #[error("line {line}: inverted range {lo}-{hi}")]
+    Inverted { line: usize, lo: i64, hi: i64 },
 }
 
-impl Assignment {
-    fn parse(s: &str) -> Self {
-        let mut iter = s.split('-');
-        Assignment {
-            low: iter.next().unwrap().parse::<i32>().unwrap(),
-            high: iter.next().unwrap().parse::<i32>().unwrap(),
-        }
-    }
+/// What to do with a range written high-low.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InvertedRanges {
+    Reject,
+    /// Swap the bounds.
+    Normalize,
+}
 
-    fn contained_within(&self, other: &Assignment) -> bool {
-        self.low <= other.low && self.high >= other.high
-    }
+fn parse_section(i: &str) -> IResult<&str, i64> {
+    map_res(digit1, |s: &str| s.parse::<i64>())(i)
+}
 
-    fn overlapping(&self, other: &Assignment) -> bool {
-        (self.low >= other.low && self.low <= other.high)
-            || (self.high >= other.low && self.high <= other.high)
-            || (self.low < other.low && self.high > other.high)
-    }
+/// Raw bounds, as written.
+type Range = (i64, i64);
+
+fn parse_range(i: &str) -> IResult<&str, Range> {
+    separated_pair(parse_section, char('-'), parse_section)(i)
 }
 
-fn parse(input: &str) -> Vec<(Assignment, Assignment)> {
+/// A whole line: `a-b,c-d`.
+fn parse_pair(i: &str) -> IResult<&str, (Range, Range)> {
+    all_consuming(separated_pair(parse_range, char(','), parse_range))(i)
+}
+
+fn parse_with(
+    input: &str,
+    policy: InvertedRanges,
+) -> Result<Vec<(Interval, Interval)>, ParseError> {
     let mut result = vec![];
-    for line in input.lines() {
-        let mut iter = line.split(',');
-        result.push((
-            Assignment::parse(iter.next().unwrap()),
-            Assignment::parse(iter.next().unwrap()),
-        ));
+    for (idx, text) in input.lines().enumerate() {
+        let line = idx + 1;
+        let (_, (first, second)) =
+            parse_pair(text.trim_end()).map_err(|_| ParseError::Malformed {
+                line,
+                text: text.to_owned(),
+            })?;
+        let interval = |(lo, hi): Range| match Interval::try_new(lo, hi) {
+            Some(iv) => Ok(iv),
+            None if policy == InvertedRanges::Normalize => Ok(Interval::new(hi, lo)),
+            None => Err(ParseError::Inverted { line, lo, hi }),
+        };
+        result.push((interval(first)?, interval(second)?));
     }
-    result
+    Ok(result)
+}
+
+fn parse(input: &str) -> Result<Vec<(Interval, Interval)>, ParseError> {
+    parse_with(input, InvertedRanges::Reject)
 }
 
-fn part1(pairs: &[(Assignment, Assignment)]) -> i32 {
+fn part1(pairs: &[(Interval, Interval)]) -> i32 {
     let mut result = 0;
     for pair in pairs {
         let (first, second) = pair;
-        if first.contained_within(second) || second.contained_within(first) {
+        if first.contains_interval(second) || second.contains_interval(first) {
             result += 1;
         }
     }
     result
 }
 
-fn part2(pairs: &[(Assignment, Assignment)]) -> i32 {
+fn part2(pairs: &[(Interval, Interval)]) -> i32 {
     let mut result = 0;
     for pair in pairs {
         let (first, second) = pair;
-        if first.overlapping(second) {
+        if first.overlaps(second) {
             result += 1;
         }
     }
     result
 }
 
-fn main() {
+/// How the two assignments in a pair relate to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Relation {
+    /// There is a gap between them.
+    Disjoint,
+    /// No section in common, but no gap either.
+    Touching,
+    /// Some sections in common.
+    Overlapping,
+    /// One lies entirely within the other.
+    Contained,
+}
+
+impl Relation {
+    fn name(&self) -> &'static str {
+        match self {
+            Relation::Disjoint => "disjoint",
+            Relation::Touching => "touching",
+            Relation::Overlapping => "overlapping",
+            Relation::Contained => "contained",
+        }
+    }
+}
+
+fn classify(first: &Interval, second: &Interval) -> Relation {
+    if first.contains_interval(second) || second.contains_interval(first) {
+        Relation::Contained
+    } else if first.overlaps(second) {
+        Relation::Overlapping
+    } else if first.touches(second) {
+        Relation::Touching
+    } else {
+        Relation::Disjoint
+    }
+}
+
+fn assignments(pairs: &[(Interval, Interval)]) -> impl Iterator<Item = &Interval> {
+    pairs.iter().flat_map(|(a, b)| [a, b])
+}
+
+/// Sections between the lowest and highest assigned that nobody covers.
+fn uncovered(pairs: &[(Interval, Interval)]) -> IntervalSet {
+    let covered: IntervalSet = assignments(pairs).copied().collect();
+    let span = covered
+        .iter()
+        .next()
+        .zip(covered.iter().last())
+        .map(|(first, last)| Interval::new(first.lo(), last.hi()));
+    IntervalSet::from_iter(span).difference(&covered)
+}
+
+/// The largest number of assignments covering any one section, and the
+/// sections where that happens, found with a sweep over the endpoints.
+fn busiest(pairs: &[(Interval, Interval)]) -> (usize, IntervalSet) {
+    // Positions are widened so that an assignment ending at `i64::MAX` can
+    // still end one past it.
+    let mut events: Vec<(i128, i32)> = assignments(pairs)
+        .flat_map(|iv| [(iv.lo().into(), 1), (i128::from(iv.hi()) + 1, -1)])
+        .collect();
+    events.sort_unstable();
+    let mut depth = 0;
+    let mut best = 0;
+    let mut sections = IntervalSet::new();
+    for (i, (at, delta)) in events.iter().enumerate() {
+        depth += delta;
+        // Only look at the depth once every event at this position is in.
+        match events.get(i + 1) {
+            Some((next, _)) if next == at => continue,
+            Some((next, _)) => {
+                let depth = depth as usize;
+                if depth > best {
+                    best = depth;
+                    sections = IntervalSet::new();
+                }
+                if depth == best && depth > 0 {
+                    sections.insert(Interval::new(*at as i64, (next - 1) as i64));
+                }
+            }
+            None => {}
+        }
+    }
+    (best, sections)
+}
+
+/// One row per pair, with its line number and relation.
+fn report_csv(pairs: &[(Interval, Interval)]) -> String {
+    let mut out = String::from("line,first,second,relation\n");
+    for (i, (first, second)) in pairs.iter().enumerate() {
+        out += &format!(
+            "{},{},{},{}\n",
+            i + 1,
+            first,
+            second,
+            classify(first, second).name()
+        );
+    }
+    out
+}
+
+fn main() -> Result<()> {
     let input = include_str!("../../input/04.txt");
-    let parsed = parse(input);
+    let args: Vec<String> = std::env::args().collect();
+    let parsed = if args.iter().any(|a| a == "--normalize") {
+        parse_with(input, InvertedRanges::Normalize)?
+    } else {
+        parse(input)?
+    };
 
     let timer = Instant::now();
     let p1 = part1(&parsed);
@@ -86,6 +220,24 @@ fn main() {
     let timer = Instant::now();
     let p2 = part2(&parsed);
     println!("Part 2: {}\n(elapsed: {:.2?})", p2, timer.elapsed());
+
+    if args.iter().any(|a| a == "--stats") {
+        let gaps = uncovered(&parsed);
+        let (depth, sections) = busiest(&parsed);
+        println!("Sections covered by no elf: {}", gaps.len());
+        for iv in gaps.iter() {
+            println!("  {}", iv);
+        }
+        println!("Most elves on one section: {}", depth);
+        for iv in sections.iter() {
+            println!("  {}", iv);
+        }
+    }
+    if args.iter().any(|a| a == "--csv") {
+        print!("{}", report_csv(&parsed));
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -99,13 +251,112 @@ mod tests {
 
     #[test]
     fn part1_works() {
-        let input = super::parse(SAMPLE);
+        let input = super::parse(SAMPLE).unwrap();
         assert_eq!(2, super::part1(&input));
     }
 
     #[test]
     fn part2_works() {
-        let input = super::parse(SAMPLE);
+        let input = super::parse(SAMPLE).unwrap();
         assert_eq!(4, super::part2(&input));
     }
+
+    #[test]
+    fn classify_works() {
+        use super::Relation::*;
+        let input = super::parse(SAMPLE).unwrap();
+        let relations: Vec<super::Relation> =
+            input.iter().map(|(a, b)| super::classify(a, b)).collect();
+        assert_eq!(
+            vec![
+                Disjoint,
+                Touching,
+                Overlapping,
+                Contained,
+                Contained,
+                Overlapping
+            ],
+            relations
+        );
+    }
+
+    #[test]
+    fn uncovered_works() {
+        let input = super::parse(SAMPLE).unwrap();
+        assert!(super::uncovered(&input).is_empty());
+        let input = super::parse("1-2,8-9\n4-4,4-5").unwrap();
+        let gaps: Vec<String> = super::uncovered(&input)
+            .iter()
+            .map(|iv| iv.to_string())
+            .collect();
+        assert_eq!(vec!["3-3", "6-7"], gaps);
+    }
+
+    #[test]
+    fn busiest_works() {
+        let input = super::parse(SAMPLE).unwrap();
+        let (depth, sections) = super::busiest(&input);
+        assert_eq!(8, depth);
+        let sections: Vec<String> = sections.iter().map(|iv| iv.to_string()).collect();
+        assert_eq!(vec!["6-6"], sections);
+
+        let input = super::parse("1-3,2-5\n3-4,9-9").unwrap();
+        let (depth, sections) = super::busiest(&input);
+        assert_eq!(3, depth);
+        let sections: Vec<String> = sections.iter().map(|iv| iv.to_string()).collect();
+        assert_eq!(vec!["3-3"], sections);
+
+        // The largest section number parses, so the sweep must cope with it.
+        let input =
+            super::parse("1-9223372036854775807,9223372036854775807-9223372036854775807").unwrap();
+        let (depth, sections) = super::busiest(&input);
+        assert_eq!(2, depth);
+        let sections: Vec<String> = sections.iter().map(|iv| iv.to_string()).collect();
+        assert_eq!(vec!["9223372036854775807-9223372036854775807"], sections);
+    }
+
+    #[test]
+    fn report_csv_works() {
+        let input = super::parse(SAMPLE).unwrap();
+        let csv = super::report_csv(&input);
+        assert!(
+            csv.starts_with("line,first,second,relation\n1,2-4,6-8,disjoint\n2,2-3,4-5,touching\n")
+        );
+        assert_eq!(7, csv.lines().count());
+    }
+
+    #[test]
+    fn malformed_pairs_are_reported() {
+        use super::ParseError::Malformed;
+        for (text, line) in [
+            ("2-4,6-8\n2-4;6-8", 2),
+            ("2-4", 1),
+            ("2-4,6-8,1-1", 1),
+            ("a-b,c-d", 1),
+        ] {
+            assert_eq!(
+                Err(Malformed {
+                    line,
+                    text: text.lines().nth(line - 1).unwrap().into()
+                }),
+                super::parse(text)
+            );
+        }
+    }
+
+    #[test]
+    fn inverted_ranges_follow_policy() {
+        use super::InvertedRanges;
+        let input = "2-4,6-8\n5-3,1-1";
+        assert_eq!(
+            Err(super::ParseError::Inverted {
+                line: 2,
+                lo: 5,
+                hi: 3
+            }),
+            super::parse_with(input, InvertedRanges::Reject)
+        );
+        let pairs = super::parse_with(input, InvertedRanges::Normalize).unwrap();
+        assert_eq!((3, 5), (pairs[1].0.lo(), pairs[1].0.hi()));
+    }
 }
@@ -14,13 +14,21 @@
 
 #![warn(clippy::all)]
 
+use anyhow::{anyhow, Result};
+use aoc22::render::{ansi_fg, Rgb, ANSI_RESET};
 use lazy_static::lazy_static;
+use pathfinding::prelude::astar;
 use regex::Regex;
-use std::time::Instant;
+use std::{
+    fmt,
+    io::{self, BufRead, Write},
+    time::Instant,
+};
+use thiserror::Error;
 
 type CrateStack = Vec<char>;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 struct Command {
     quantity: usize,
     from: usize,
@@ -28,113 +36,454 @@ struct Command {
 }
 
 impl Command {
-    fn parse(s: &str) -> Command {
+    fn parse(s: &str) -> Option<Command> {
         lazy_static! {
-            static ref RE: Regex = Regex::new(r"move (\d+) from (\d+) to (\d+)").unwrap();
+            static ref RE: Regex = Regex::new(r"^move (\d+) from (\d+) to (\d+)$").unwrap();
         }
-        let cap = RE.captures(s).unwrap();
-        Command {
-            quantity: cap[1].parse().unwrap(),
-            from: cap[2].parse().unwrap(),
-            to: cap[3].parse().unwrap(),
+        let cap = RE.captures(s.trim())?;
+        Some(Command {
+            quantity: cap[1].parse().ok()?,
+            from: cap[2].parse().ok()?,
+            to: cap[3].parse().ok()?,
+        })
+    }
+
+    /// Checks that the move makes sense for the yard as it currently is.
+    /// `line` is only used for the error.
+    fn validate(&self, yard: &Yard, line: usize) -> Result<(), MoveError> {
+        for stack in [self.from, self.to] {
+            if stack == 0 || stack > yard.stacks.len() {
+                return Err(MoveError::NoSuchStack { line, stack });
+            }
         }
+        let available = yard.stacks[self.from - 1].len();
+        if self.quantity > available {
+            return Err(MoveError::NotEnoughCrates {
+                line,
+                command: *self,
+                available,
+            });
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-struct Puzzle {
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "move {} from {} to {}",
+            self.quantity, self.from, self.to
+        )
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+enum MoveError {
+    #[error("line {line}: there is no stack {stack}")]
+    NoSuchStack { line: usize, stack: usize },
+    #[error("line {line}: cannot {command}, stack {} only holds {available}", .command.from)]
+    NotEnoughCrates {
+        line: usize,
+        command: Command,
+        available: usize,
+    },
+}
+
+/// The stacks of crates, each listed bottom to top.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+struct Yard {
     stacks: Vec<CrateStack>,
-    commands: Vec<Command>,
-}
-
-fn parse(input: &str) -> Puzzle {
-    let mut lines = input.lines();
-    let mut commands: Vec<Command> = vec![];
-    let mut stacks: Vec<CrateStack> = vec![];
-    loop {
-        let line = lines.next().unwrap();
-        let chars: Vec<char> = line.chars().collect();
-        if chars[1] == '1' {
-            break;
-        }
-        for (stack, c) in chars.chunks(4).enumerate() {
-            if stacks.len() <= stack {
-                stacks.push(vec![]);
-            }
-            if c[1] != ' ' {
-                stacks[stack].push(c[1])
-            }
+}
+
+impl Yard {
+    /// The crate on top of each stack, skipping empty stacks.
+    fn tops(&self) -> String {
+        self.stacks.iter().filter_map(|s| s.last()).collect()
+    }
+}
+
+/// A crane model: how it carries out a single move.
+trait Crane {
+    fn apply(&self, yard: &mut Yard, command: &Command);
+}
+
+/// Moves crates one at a time, reversing their order.
+struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn apply(&self, yard: &mut Yard, command: &Command) {
+        for _ in 0..command.quantity {
+            let c = yard.stacks[command.from - 1].pop().unwrap();
+            yard.stacks[command.to - 1].push(c);
         }
     }
-    // Skip blank line.
-    assert_eq!(0, lines.next().unwrap().len());
-    for line in lines {
-        commands.push(Command::parse(line));
+}
+
+/// Moves crates as a block, keeping their order.
+struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn apply(&self, yard: &mut Yard, command: &Command) {
+        let from = &mut yard.stacks[command.from - 1];
+        let moved = from.split_off(from.len() - command.quantity);
+        yard.stacks[command.to - 1].extend(moved);
     }
-    // Now reverse all the stacks, since we built them upside-down.
-    for stack in &mut stacks {
-        stack.reverse();
+}
+
+/// Looks up a crane by its model number.
+fn crane(model: &str) -> Option<Box<dyn Crane>> {
+    match model {
+        "9000" => Some(Box::new(CrateMover9000)),
+        "9001" => Some(Box::new(CrateMover9001)),
+        _ => None,
     }
+}
 
-    Puzzle { stacks, commands }
+#[derive(Debug, PartialEq, Clone)]
+struct Puzzle {
+    yard: Yard,
+    /// Each command with the input line it came from.
+    commands: Vec<(usize, Command)>,
 }
 
-fn part1(puzzle: &Puzzle) -> String {
-    let mut puzzle = (*puzzle).clone();
+/// Runs every command with the given crane, returning the final yard, or
+/// the first command that cannot be carried out.
+fn operate(puzzle: &Puzzle, crane: &dyn Crane) -> Result<Yard, MoveError> {
+    let mut yard = puzzle.yard.clone();
+    for (line, command) in &puzzle.commands {
+        command.validate(&yard, *line)?;
+        crane.apply(&mut yard, command);
+    }
+    Ok(yard)
+}
 
-    for command in &puzzle.commands {
-        for _ in 0..command.quantity {
-            let c = puzzle.stacks[command.from - 1].pop().unwrap();
-            puzzle.stacks[command.to - 1].push(c);
+#[derive(Debug, Error, PartialEq, Eq)]
+enum ParseError {
+    #[error("line {line}: invalid command `{text}`")]
+    BadCommand { line: usize, text: String },
+    #[error("drawing has no stack label row")]
+    MissingLabels,
+    #[error("line {line}: stack labels must be 1, 2, 3, ... in order")]
+    BadLabels { line: usize },
+    #[error("line {line}: malformed crate row `{text}`")]
+    BadRow { line: usize, text: String },
+    #[error("line {line}: crate at column {column} is not above a stack label")]
+    Unaligned { line: usize, column: usize },
+}
+
+/// Parses the crate drawing, using the label row to find each stack's
+/// column. Rows may have their trailing whitespace trimmed, and labels may
+/// have more than one digit. Returns the yard and the number of lines used.
+fn parse_drawing(lines: &[&str]) -> Result<(Yard, usize), ParseError> {
+    let label_idx = lines
+        .iter()
+        .position(|l| l.trim_start().starts_with(|c: char| c.is_ascii_digit()))
+        .ok_or(ParseError::MissingLabels)?;
+    // The (first, last) column of each label.
+    let mut labels: Vec<(usize, usize)> = vec![];
+    for (col, ch) in lines[label_idx].char_indices() {
+        match labels.last_mut() {
+            _ if ch == ' ' => {}
+            Some((_, end)) if *end + 1 == col => *end = col,
+            _ => labels.push((col, col)),
         }
     }
+    let expected = (1..=labels.len()).map(|n| n.to_string());
+    if !lines[label_idx].split_whitespace().eq(expected) {
+        return Err(ParseError::BadLabels {
+            line: label_idx + 1,
+        });
+    }
 
-    let mut result = vec![];
-    for stack in puzzle.stacks {
-        result.push(*stack.last().unwrap());
+    let mut stacks: Vec<CrateStack> = vec![vec![]; labels.len()];
+    for (idx, text) in lines[..label_idx].iter().enumerate().rev() {
+        let line = idx + 1;
+        let bad_row = || ParseError::BadRow {
+            line,
+            text: text.to_string(),
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let mut col = 0;
+        while col < chars.len() {
+            match chars[col..] {
+                [' ', ..] => col += 1,
+                ['[', c, ']', ..] if c != ' ' => {
+                    let stack = labels
+                        .iter()
+                        .position(|(start, end)| *start <= col + 2 && col <= *end)
+                        .ok_or(ParseError::Unaligned {
+                            line,
+                            column: col + 1,
+                        })?;
+                    stacks[stack].push(c);
+                    col += 3;
+                }
+                _ => return Err(bad_row()),
+            }
+        }
     }
-    result.iter().collect()
+    Ok((Yard { stacks }, label_idx + 1))
 }
 
-fn part2(puzzle: &Puzzle) -> String {
-    let mut puzzle = (*puzzle).clone();
+/// Draws the yard exactly as the puzzle does, label row included.
+fn render(yard: &Yard) -> String {
+    render_with(yard, |_, _| false)
+}
 
-    for command in &puzzle.commands {
-        let mut tmp = vec![];
-        // HORRIBLE hack!
-        for _ in 0..command.quantity {
-            let c = puzzle.stacks[command.from - 1].pop().unwrap();
-            tmp.push(c);
+/// Like `render`, but draws the crates for which `highlight(stack, level)`
+/// holds in bold yellow.
+/// Colour of the crates `render_with` highlights.
+const HIGHLIGHT: Rgb = [255, 215, 0];
+
+fn render_with<F>(yard: &Yard, highlight: F) -> String
+where
+    F: Fn(usize, usize) -> bool,
+{
+    let height = yard.stacks.iter().map(|s| s.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for level in (0..height).rev() {
+        let row: Vec<String> = yard
+            .stacks
+            .iter()
+            .enumerate()
+            .map(|(stack, s)| match s.get(level) {
+                Some(c) if highlight(stack, level) => {
+                    format!("{}[{}]{}", ansi_fg(HIGHLIGHT), c, ANSI_RESET)
+                }
+                Some(c) => format!("[{}]", c),
+                None => "   ".into(),
+            })
+            .collect();
+        out += &row.join(" ");
+        out.push('\n');
+    }
+    let labels: Vec<String> = (1..=yard.stacks.len())
+        .map(|n| format!("{:^3}", n))
+        .collect();
+    out += &labels.join(" ");
+    out.push('\n');
+    out
+}
+
+/// Steps through the puzzle one move at a time, printing the yard after each
+/// move with the moved crates highlighted. An empty line on `input` moves on,
+/// `c` runs to the end without stopping and `q` quits.
+fn debug<R: BufRead, W: Write>(
+    puzzle: &Puzzle,
+    crane: &dyn Crane,
+    mut input: R,
+    mut output: W,
+) -> Result<Yard> {
+    let mut yard = puzzle.yard.clone();
+    let mut stopping = true;
+    write!(output, "{}", render(&yard))?;
+    for (line, command) in &puzzle.commands {
+        if stopping {
+            write!(output, "line {}: {} ", line, command)?;
+            output.flush()?;
+            let mut answer = String::new();
+            input.read_line(&mut answer)?;
+            match answer.trim() {
+                "q" => break,
+                "c" => stopping = false,
+                _ => {}
+            }
         }
-        for _ in 0..command.quantity {
-            let c = tmp.pop().unwrap();
-            puzzle.stacks[command.to - 1].push(c);
+        command.validate(&yard, *line)?;
+        crane.apply(&mut yard, command);
+        let to = command.to - 1;
+        let moved = yard.stacks[to].len() - command.quantity;
+        write!(
+            output,
+            "{}",
+            render_with(&yard, |stack, level| stack == to && level >= moved)
+        )?;
+    }
+    Ok(yard)
+}
+
+fn parse(input: &str) -> Result<Puzzle, ParseError> {
+    let lines: Vec<&str> = input.lines().collect();
+    let (yard, used) = parse_drawing(&lines)?;
+    let mut commands = vec![];
+    for (idx, text) in lines.iter().enumerate().skip(used) {
+        if text.trim().is_empty() {
+            continue;
         }
+        let command = Command::parse(text).ok_or_else(|| ParseError::BadCommand {
+            line: idx + 1,
+            text: text.to_string(),
+        })?;
+        commands.push((idx + 1, command));
+    }
+    Ok(Puzzle { yard, commands })
+}
+
+/// Every legal move in the yard, with the yard it leads to.
+fn moves<'a>(yard: &'a Yard, crane: &'a dyn Crane) -> impl Iterator<Item = (Command, Yard)> + 'a {
+    let stacks = yard.stacks.len();
+    (1..=stacks)
+        .flat_map(move |from| (1..=stacks).map(move |to| (from, to)))
+        .filter(|(from, to)| from != to)
+        .flat_map(move |(from, to)| {
+            (1..=yard.stacks[from - 1].len()).map(move |quantity| Command { quantity, from, to })
+        })
+        .map(move |command| {
+            let mut next = yard.clone();
+            crane.apply(&mut next, &command);
+            (command, next)
+        })
+}
+
+/// How many yards `plan` may consider before giving up.
+const PLAN_LIMIT: usize = 200_000;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+enum PlanError {
+    #[error("no sequence of moves produces {0}")]
+    Impossible(String),
+    #[error("gave up after considering {0} yards")]
+    TooManyYards(usize),
+}
+
+/// A lower bound on the moves left before the tops spell `target`. A move
+/// changes the tops of at most two stacks, so when every stack has a letter
+/// in `target` it is half the mismatched stacks, rounded up. Otherwise the
+/// empty stacks could be anywhere, and all that is known is whether the
+/// target is already met.
+fn moves_left(yard: &Yard, target: &str) -> usize {
+    if target.chars().count() != yard.stacks.len() {
+        return (yard.tops() != target) as usize;
     }
+    let mismatched = yard
+        .stacks
+        .iter()
+        .zip(target.chars())
+        .filter(|(stack, c)| stack.last() != Some(c))
+        .count();
+    mismatched.div_ceil(2)
+}
 
-    let mut result = vec![];
-    for stack in puzzle.stacks {
-        result.push(*stack.last().unwrap());
+/// Searches for a shortest list of moves after which the top crates spell
+/// `target`, using the given crane. The search is A* over whole yards, so it
+/// gives up once it has considered `limit` of them.
+fn plan(
+    yard: &Yard,
+    target: &str,
+    crane: &dyn Crane,
+    limit: usize,
+) -> Result<Vec<Command>, PlanError> {
+    let impossible = || PlanError::Impossible(target.to_owned());
+    // Moves never create or destroy crates, so give up early if the target
+    // needs crates that aren't there, more tops than there are stacks, or no
+    // tops at all while crates remain.
+    let mut available: Vec<char> = yard.stacks.iter().flatten().copied().collect();
+    if target.chars().count() > yard.stacks.len() || (target.is_empty() && !available.is_empty()) {
+        return Err(impossible());
+    }
+    for c in target.chars() {
+        let i = available
+            .iter()
+            .position(|a| *a == c)
+            .ok_or_else(impossible)?;
+        available.swap_remove(i);
+    }
+
+    // Once over the limit, stop expanding yards; the open set then drains,
+    // and whatever A* returns is no longer known to be shortest.
+    let mut considered = 0;
+    let mut cut_short = false;
+    let path = astar(
+        yard,
+        |y| {
+            if considered >= limit {
+                cut_short = true;
+                return vec![];
+            }
+            let next: Vec<(Yard, usize)> = moves(y, crane).map(|(_, next)| (next, 1)).collect();
+            considered += next.len();
+            next
+        },
+        |y| moves_left(y, target),
+        |y| y.tops() == target,
+    );
+    if cut_short {
+        return Err(PlanError::TooManyYards(considered));
     }
-    result.iter().collect()
+    let (path, _) = path.ok_or_else(impossible)?;
+    Ok(path
+        .windows(2)
+        .map(|pair| {
+            moves(&pair[0], crane)
+                .find(|(_, next)| *next == pair[1])
+                .unwrap()
+                .0
+        })
+        .collect())
 }
 
-fn main() {
+fn part1(puzzle: &Puzzle) -> Result<String, MoveError> {
+    Ok(operate(puzzle, &CrateMover9000)?.tops())
+}
+
+fn part2(puzzle: &Puzzle) -> Result<String, MoveError> {
+    Ok(operate(puzzle, &CrateMover9001)?.tops())
+}
+
+fn main() -> Result<()> {
     let input = include_str!("../../input/05.txt");
-    let parsed = parse(input);
+    let parsed = parse(input)?;
 
     let timer = Instant::now();
-    let p1 = part1(&parsed);
+    let p1 = part1(&parsed)?;
     println!("Part 1: {:?}\n(elapsed: {:.2?})", p1, timer.elapsed());
 
     let timer = Instant::now();
-    let p2 = part2(&parsed);
+    let p2 = part2(&parsed)?;
     println!("Part 2: {:?}\n(elapsed: {:.2?})", p2, timer.elapsed());
+
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(model) = args.iter().skip_while(|a| *a != "--crane").nth(1) {
+        match crane(model) {
+            Some(crane) => println!(
+                "CrateMover {}: {:?}",
+                model,
+                operate(&parsed, crane.as_ref())?.tops()
+            ),
+            None => println!("Unknown crane model: {}", model),
+        }
+    }
+    if args.iter().any(|a| a == "--render") {
+        print!("{}", render(&operate(&parsed, &CrateMover9000)?));
+    }
+    let model = args
+        .iter()
+        .skip_while(|a| *a != "--crane")
+        .nth(1)
+        .map_or("9000", |m| m.as_str());
+    let chosen = || crane(model).ok_or_else(|| anyhow!("unknown crane model: {}", model));
+    if let Some(target) = args.iter().skip_while(|a| *a != "--plan").nth(1) {
+        for command in plan(&parsed.yard, target, chosen()?.as_ref(), PLAN_LIMIT)? {
+            println!("{}", command);
+        }
+    }
+    if args.iter().any(|a| a == "--step") {
+        debug(
+            &parsed,
+            chosen()?.as_ref(),
+            io::stdin().lock(),
+            io::stdout(),
+        )?;
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Command;
+    use crate::{Command, Crane, MoveError, ParseError, PlanError, Yard, PLAN_LIMIT};
+    use aoc22::render::{ansi_fg, ANSI_RESET};
 
     const SAMPLE: &str = r"    [D]    
 [N] [C]    
@@ -154,19 +503,246 @@ move 1 from 1 to 2";
                 from: 333,
                 to: 1
             },
-            Command::parse("move 12 from 333 to 1")
+            Command::parse("move 12 from 333 to 1").unwrap()
         );
     }
 
     #[test]
     fn part1_works() {
-        let input = super::parse(SAMPLE);
-        assert_eq!("CMZ", super::part1(&input));
+        let input = super::parse(SAMPLE).unwrap();
+        assert_eq!("CMZ", super::part1(&input).unwrap());
     }
 
     #[test]
     fn part2_works() {
-        let input = super::parse(SAMPLE);
-        assert_eq!("MCD", super::part2(&input));
+        let input = super::parse(SAMPLE).unwrap();
+        assert_eq!("MCD", super::part2(&input).unwrap());
+    }
+
+    #[test]
+    fn cranes_work() {
+        let command = Command {
+            quantity: 2,
+            from: 1,
+            to: 2,
+        };
+        let start = Yard {
+            stacks: vec![vec!['A', 'B', 'C'], vec![]],
+        };
+
+        let mut yard = start.clone();
+        super::CrateMover9000.apply(&mut yard, &command);
+        assert_eq!(vec![vec!['A'], vec!['C', 'B']], yard.stacks);
+
+        let mut yard = start;
+        super::CrateMover9001.apply(&mut yard, &command);
+        assert_eq!(vec![vec!['A'], vec!['B', 'C']], yard.stacks);
+        assert_eq!("AC", yard.tops());
+    }
+
+    #[test]
+    fn custom_crane_works() {
+        /// A broken crane that only ever moves one crate.
+        struct Lazy;
+
+        impl Crane for Lazy {
+            fn apply(&self, yard: &mut Yard, command: &Command) {
+                let c = yard.stacks[command.from - 1].pop().unwrap();
+                yard.stacks[command.to - 1].push(c);
+            }
+        }
+
+        let input = super::parse(SAMPLE).unwrap();
+        assert_eq!("NCD", super::operate(&input, &Lazy).unwrap().tops());
+        assert!(super::crane("9001").is_some());
+        assert!(super::crane("3000").is_none());
+    }
+
+    #[test]
+    fn render_round_trips() {
+        let input = super::parse(SAMPLE).unwrap();
+        let drawing = super::render(&input.yard);
+        assert!(SAMPLE.starts_with(&drawing));
+        let lines: Vec<&str> = drawing.lines().collect();
+        assert_eq!((input.yard, 4), super::parse_drawing(&lines).unwrap());
+    }
+
+    #[test]
+    fn trimmed_drawing_works() {
+        let trimmed: Vec<&str> = SAMPLE.lines().map(|l| l.trim_end()).collect();
+        let input = super::parse(&trimmed.join("\n")).unwrap();
+        assert_eq!(super::parse(SAMPLE).unwrap(), input);
+    }
+
+    #[test]
+    fn many_stacks_work() {
+        let yard = Yard {
+            stacks: (0..12)
+                .map(|n| vec![(b'A' + n as u8) as char; n % 3])
+                .collect(),
+        };
+        let drawing = super::render(&yard);
+        assert!(drawing.ends_with(" 9  10  11  12 \n"));
+        let lines: Vec<&str> = drawing.lines().map(|l| l.trim_end()).collect();
+        assert_eq!(yard, super::parse_drawing(&lines).unwrap().0);
+    }
+
+    #[test]
+    fn malformed_drawings_are_reported() {
+        let parse = |s: &str| super::parse_drawing(&s.lines().collect::<Vec<_>>());
+        assert_eq!(Err(ParseError::MissingLabels), parse("[A]\n"));
+        assert_eq!(
+            Err(ParseError::BadLabels { line: 2 }),
+            parse("[A] [B]\n 1   3 ")
+        );
+        assert_eq!(
+            Err(ParseError::BadRow {
+                line: 1,
+                text: "[A] B".into()
+            }),
+            parse("[A] B\n 1   2 ")
+        );
+        assert_eq!(
+            Err(ParseError::Unaligned { line: 1, column: 9 }),
+            parse("[A]     [B]\n 1   2 ")
+        );
+    }
+
+    #[test]
+    fn bad_commands_are_reported() {
+        assert_eq!(None, Command::parse("move 1 from 2"));
+        assert_eq!(
+            Err(ParseError::BadCommand {
+                line: 6,
+                text: "lift 1 from 2 to 1".into()
+            }),
+            super::parse(&SAMPLE.replace("move 1 from 2 to 1", "lift 1 from 2 to 1"))
+        );
+    }
+
+    #[test]
+    fn invalid_moves_are_reported() {
+        let input = super::parse(&SAMPLE.replace("move 3 from 1", "move 4 from 1")).unwrap();
+        let err = super::part1(&input).unwrap_err();
+        assert_eq!(
+            MoveError::NotEnoughCrates {
+                line: 7,
+                command: Command {
+                    quantity: 4,
+                    from: 1,
+                    to: 3
+                },
+                available: 3
+            },
+            err
+        );
+        assert_eq!(
+            "line 7: cannot move 4 from 1 to 3, stack 1 only holds 3",
+            err.to_string()
+        );
+
+        let input =
+            super::parse(&SAMPLE.replace("from 2 to 1\nmove 3", "from 0 to 1\nmove 3")).unwrap();
+        assert_eq!(
+            Err(MoveError::NoSuchStack { line: 6, stack: 0 }),
+            super::part2(&input)
+        );
+        let input = super::parse(&SAMPLE.replace("to 2", "to 4")).unwrap();
+        assert_eq!(
+            Err(MoveError::NoSuchStack { line: 9, stack: 4 }),
+            super::part2(&input)
+        );
+    }
+
+    #[test]
+    fn debug_works() {
+        let input = super::parse(SAMPLE).unwrap();
+        let mut output = vec![];
+        let yard = super::debug(
+            &input,
+            &super::CrateMover9001,
+            "\nq\n".as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("line 6: move 1 from 2 to 1 "));
+        let highlighted = format!("{}[D]{}", ansi_fg(super::HIGHLIGHT), ANSI_RESET);
+        assert!(output.contains(&format!("{}        \n[N] [C]    \n", highlighted)));
+        assert!(output.ends_with("line 7: move 3 from 1 to 3 "));
+        assert_eq!("DCP", yard.tops());
+
+        let mut output = vec![];
+        let yard = super::debug(
+            &input,
+            &super::CrateMover9001,
+            "c\n".as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!("MCD", yard.tops());
+    }
+
+    #[test]
+    fn plan_works() {
+        let input = super::parse(SAMPLE).unwrap();
+        let cranes: [&dyn Crane; 2] = [&super::CrateMover9000, &super::CrateMover9001];
+        for crane in cranes {
+            assert_eq!(
+                Ok(vec![]),
+                super::plan(&input.yard, "NDP", crane, PLAN_LIMIT)
+            );
+
+            // N, C and D have to be buried under Z, M and P.
+            let commands = super::plan(&input.yard, "ZMP", crane, PLAN_LIMIT).unwrap();
+            assert_eq!(3, commands.len());
+            let mut yard = input.yard.clone();
+            for command in &commands {
+                crane.apply(&mut yard, command);
+            }
+            assert_eq!("ZMP", yard.tops());
+        }
+
+        // The 9001 can lift N and Z together, keeping Z underneath.
+        let plan = super::plan(&input.yard, "NP", &super::CrateMover9001, PLAN_LIMIT).unwrap();
+        assert_eq!(1, plan.len());
+
+        assert_eq!(
+            Err(PlanError::Impossible("XYZ".into())),
+            super::plan(&input.yard, "XYZ", &super::CrateMover9000, PLAN_LIMIT)
+        );
+        assert_eq!(
+            Err(PlanError::Impossible("NDPZ".into())),
+            super::plan(&input.yard, "NDPZ", &super::CrateMover9000, PLAN_LIMIT)
+        );
+        assert_eq!(
+            Err(PlanError::Impossible("".into())),
+            super::plan(&input.yard, "", &super::CrateMover9000, PLAN_LIMIT)
+        );
+    }
+
+    #[test]
+    fn plan_gives_up_on_big_yards() {
+        let lines = [
+            "        [e]                        ",
+            "        [X]         [U]            ",
+            "        [H]     [F] [T]         [b]",
+            "    [B] [K]     [I] [E]     [Z] [x]",
+            "    [h] [D] [j] [N] [C]     [o] [k]",
+            "[v] [p] [u] [G] [t] [a] [R] [g] [y]",
+            "[Q] [s] [P] [Y] [l] [M] [V] [c] [z]",
+            "[n] [W] [d] [q] [A] [i] [O] [f] [w]",
+            " 1   2   3   4   5   6   7   8   9 ",
+        ];
+        let (yard, _) = super::parse_drawing(&lines).unwrap();
+        let crane = &super::CrateMover9001;
+        // Swapping two tops takes three moves, which A* finds quickly.
+        let commands = super::plan(&yard, "BvejFURZb", crane, PLAN_LIMIT).unwrap();
+        assert_eq!(3, commands.len());
+        // Swapping two pairs takes too long, and is reported as such.
+        assert!(matches!(
+            super::plan(&yard, "BvjeFURZb", crane, PLAN_LIMIT),
+            Err(PlanError::TooManyYards(_))
+        ));
     }
 }
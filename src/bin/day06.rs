@@ -14,51 +14,165 @@
 
 #![warn(clippy::all)]
 
-use std::time::Instant;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    io::{self, Read},
+    time::Instant,
+};
+use unicode_segmentation::UnicodeSegmentation;
 
-fn parse(input: &str) -> Vec<char> {
-    input.chars().collect()
+/// The datastream without its line terminator (`\n`, `\r\n` or a lone `\r`),
+/// which would otherwise count as part of the stream.
+fn datastream(input: &str) -> &str {
+    let input = input.strip_suffix('\n').unwrap_or(input);
+    input.strip_suffix('\r').unwrap_or(input)
 }
 
-fn part1(puzzle: &[char]) -> i32 {
-    let mut marker: [char; 4] = puzzle[0..4].try_into().unwrap();
-    for (idx, ch) in puzzle.iter().enumerate() {
-        marker[idx % 4] = *ch;
-        let mut dup = false;
-        for i in 0..4 {
-            for j in i + 1..4 {
-                if marker[i] == marker[j] {
-                    dup = true;
-                }
-            }
+fn parse(input: &str) -> Vec<u8> {
+    datastream(input).as_bytes().to_vec()
+}
+
+/// What counts as one character of the datastream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Byte,
+    Char,
+    Grapheme,
+}
+
+/// Like `find_marker`, for items that don't fit the byte table.
+fn first_distinct_run<T, I>(items: I, k: usize) -> Option<usize>
+where
+    T: Hash + Eq,
+    I: IntoIterator<Item = T>,
+{
+    assert!(k > 0, "a marker needs at least one item");
+    let mut last_seen: HashMap<T, usize> = HashMap::new();
+    let mut start = 0;
+    for (i, item) in items.into_iter().enumerate() {
+        if let Some(prev) = last_seen.insert(item, i) {
+            start = start.max(prev + 1);
+        }
+        if i + 1 - start >= k {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+/// Finds the first marker of `k` distinct units in `text`, with the position
+/// counted in those units.
+fn find_marker_as(text: &str, k: usize, unit: Unit) -> Option<usize> {
+    match unit {
+        Unit::Byte => find_marker(text.as_bytes(), k),
+        Unit::Char => first_distinct_run(text.chars(), k),
+        Unit::Grapheme => first_distinct_run(text.graphemes(true), k),
+    }
+}
+
+/// Tracks the most recent run of distinct bytes in a stream, one byte at a
+/// time, in constant time per byte.
+///
+/// The window `start..pos` never contains a repeat: when a byte was last
+/// seen inside the window, the window restarts just after that occurrence.
+struct MarkerDetector {
+    k: usize,
+    last_seen: [Option<usize>; 256],
+    start: usize,
+    pos: usize,
+}
+
+impl MarkerDetector {
+    fn new(k: usize) -> Self {
+        assert!(k > 0, "a marker needs at least one byte");
+        MarkerDetector {
+            k,
+            last_seen: [None; 256],
+            start: 0,
+            pos: 0,
         }
-        if !dup {
-            return idx as i32 + 1;
+    }
+
+    /// Feeds in the next byte, returning the number of bytes consumed so far
+    /// if the last `k` of them are all different.
+    fn push(&mut self, b: u8) -> Option<usize> {
+        if let Some(prev) = self.last_seen[b as usize] {
+            self.start = self.start.max(prev + 1);
         }
+        self.last_seen[b as usize] = Some(self.pos);
+        self.pos += 1;
+        (self.pos - self.start >= self.k).then_some(self.pos)
     }
-    0
 }
 
-fn part2(puzzle: &[char]) -> i32 {
-    let mut marker: [char; 14] = puzzle[0..14].try_into().unwrap();
-    for (idx, ch) in puzzle.iter().enumerate() {
-        marker[idx % 14] = *ch;
-        let mut dup = false;
-        for i in 0..14 {
-            for j in i + 1..14 {
-                if marker[i] == marker[j] {
-                    dup = true;
+/// Every position at which a marker of length `k` ends.
+fn markers<I: IntoIterator<Item = u8>>(bytes: I, k: usize) -> impl Iterator<Item = usize> {
+    let mut detector = MarkerDetector::new(k);
+    bytes.into_iter().filter_map(move |b| detector.push(b))
+}
+
+/// Like `markers`, but reads the stream in chunks, so it never needs to
+/// hold all of it in memory.
+struct ReadMarkers<R: Read> {
+    reader: R,
+    detector: MarkerDetector,
+    buf: Box<[u8]>,
+    filled: usize,
+    next: usize,
+}
+
+impl<R: Read> ReadMarkers<R> {
+    fn new(reader: R, k: usize) -> Self {
+        ReadMarkers {
+            reader,
+            detector: MarkerDetector::new(k),
+            buf: vec![0; 4096].into_boxed_slice(),
+            filled: 0,
+            next: 0,
+        }
+    }
+}
+
+impl<R: Read> Iterator for ReadMarkers<R> {
+    type Item = io::Result<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while self.next < self.filled {
+                let b = self.buf[self.next];
+                self.next += 1;
+                if let Some(pos) = self.detector.push(b) {
+                    return Some(Ok(pos));
                 }
             }
-        }
-        if !dup {
-            return idx as i32 + 1;
+            match self.reader.read(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(n) => {
+                    self.filled = n;
+                    self.next = 0;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Some(Err(e)),
+            }
         }
     }
-    0
 }
 
-fn main() {
+/// Finds the end of the first run of `k` distinct bytes.
+fn find_marker(puzzle: &[u8], k: usize) -> Option<usize> {
+    markers(puzzle.iter().copied(), k).next()
+}
+
+fn part1(puzzle: &[u8]) -> Option<usize> {
+    find_marker(puzzle, 4)
+}
+
+fn part2(puzzle: &[u8]) -> Option<usize> {
+    find_marker(puzzle, 14)
+}
+
+fn main() -> io::Result<()> {
     let input = include_str!("../../input/06.txt");
     let parsed = parse(input);
 
@@ -69,21 +183,148 @@ fn main() {
     let timer = Instant::now();
     let p2 = part2(&parsed);
     println!("Part 2: {:?}\n(elapsed: {:.2?})", p2, timer.elapsed());
+
+    let args: Vec<String> = std::env::args().collect();
+    let unit = if args.iter().any(|a| a == "--chars") {
+        Unit::Char
+    } else if args.iter().any(|a| a == "--graphemes") {
+        Unit::Grapheme
+    } else {
+        Unit::Byte
+    };
+    if unit != Unit::Byte {
+        let text = datastream(input);
+        println!("Part 1 ({:?}): {:?}", unit, find_marker_as(text, 4, unit));
+        println!("Part 2 ({:?}): {:?}", unit, find_marker_as(text, 14, unit));
+    }
+
+    if args.iter().any(|a| a == "--stdin") {
+        let first = ReadMarkers::new(io::stdin().lock(), 14)
+            .next()
+            .transpose()?;
+        println!("First start-of-message marker on stdin: {:?}", first);
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+
     const SAMPLE: &str = r"mjqjpqmgbljsphdztnvjfqwrcgsmlb";
 
     #[test]
     fn part1_works() {
         let input = super::parse(SAMPLE);
-        assert_eq!(7, super::part1(&input));
+        assert_eq!(Some(7), super::part1(&input));
     }
 
     #[test]
     fn part2_works() {
         let input = super::parse(SAMPLE);
-        assert_eq!(19, super::part2(&input));
+        assert_eq!(Some(19), super::part2(&input));
+    }
+
+    #[test]
+    fn more_samples_work() {
+        for (sample, p1, p2) in [
+            ("bvwbjplbgvbhsrlpgdmjqwftvncz", 5, 23),
+            ("nppdvjthqldpwncqszvftbrmjlhg", 6, 23),
+            ("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 10, 29),
+            ("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 11, 26),
+        ] {
+            let input = super::parse(sample);
+            assert_eq!(Some(p1), super::part1(&input));
+            assert_eq!(Some(p2), super::part2(&input));
+        }
+    }
+
+    #[test]
+    fn any_bytes_work() {
+        assert_eq!(Some(4), super::find_marker(&[0, 255, 0, 7], 3));
+        assert_eq!(Some(1), super::find_marker(b"x", 1));
+        assert_eq!(None, super::find_marker(b"aaaa", 2));
+        assert_eq!(None, super::find_marker(b"ab", 3));
+    }
+
+    #[test]
+    fn all_markers_work() {
+        let found: Vec<usize> = super::markers(*b"abcabcaab", 3).collect();
+        assert_eq!(vec![3, 4, 5, 6, 7], found);
+        assert_eq!(None, super::markers(*b"ab", 3).next());
+        assert_eq!(None, super::markers(std::iter::empty(), 4).next());
+    }
+
+    /// Hands out at most `n` bytes per read, to exercise chunk boundaries.
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.0.len().min(self.1).min(buf.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn read_markers_work() {
+        let sample = SAMPLE.as_bytes();
+        let expected: Vec<usize> = super::markers(sample.iter().copied(), 14).collect();
+        for chunk in [1, 3, 4096] {
+            let found: Vec<usize> = super::ReadMarkers::new(Trickle(sample, chunk), 14)
+                .collect::<std::io::Result<_>>()
+                .unwrap();
+            assert_eq!(expected, found);
+        }
+        assert_eq!(Some(19), expected.first().copied());
+        assert!(super::ReadMarkers::new(&b"abcabc"[..], 4).next().is_none());
+    }
+
+    #[test]
+    fn line_terminators_are_ignored() {
+        use super::datastream;
+        assert_eq!("abc", datastream("abc\n"));
+        assert_eq!("abc", datastream("abc\r\n"));
+        assert_eq!("abc", datastream("abc\r"));
+        assert_eq!("abc", datastream("abc"));
+        // Counting the terminator would find a marker that isn't there.
+        assert_eq!(None, super::part1(&super::parse("abc\r\n")));
+        assert_eq!(None, super::part1(&super::parse("abc\n")));
+        assert_eq!(Some(4), super::part1(&super::parse("abcd\r\n")));
+        let crlf = format!("{}\r\n", SAMPLE);
+        assert_eq!(Some(7), super::part1(&super::parse(&crlf)));
+        assert_eq!(Some(19), super::part2(&super::parse(&crlf)));
+    }
+
+    #[test]
+    fn units_work() {
+        use super::{find_marker_as, Unit};
+        // 'é' is two bytes, both repeated.
+        assert_eq!(Some(5), find_marker_as("ééab", 3, Unit::Byte));
+        assert_eq!(Some(4), find_marker_as("ééab", 3, Unit::Char));
+        assert_eq!(Some(4), find_marker_as("ééab", 3, Unit::Grapheme));
+        // 'e' followed by a combining acute accent is two chars, one grapheme.
+        let text = "e\u{301}eab";
+        assert_eq!(Some(4), find_marker_as(text, 3, Unit::Char));
+        assert_eq!(Some(3), find_marker_as(text, 3, Unit::Grapheme));
+        assert_eq!(Some(7), find_marker_as(SAMPLE, 4, Unit::Char));
+        assert_eq!(None, find_marker_as("日本日本", 3, Unit::Char));
+    }
+
+    proptest! {
+        #[test]
+        fn find_marker_matches_windows(
+            puzzle in prop::collection::vec(0..6u8, 0..40),
+            k in 1..8usize,
+        ) {
+            let expected = puzzle
+                .windows(k)
+                .position(|w| w.iter().collect::<HashSet<_>>().len() == k)
+                .map(|i| i + k);
+            prop_assert_eq!(expected, super::find_marker(&puzzle, k));
+        }
     }
 }
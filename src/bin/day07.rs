@@ -14,96 +14,698 @@
 
 #![warn(clippy::all)]
 
-use std::{collections::HashMap, time::Instant};
+use anyhow::{anyhow, Result};
+use std::{
+    cell::OnceCell,
+    collections::HashSet,
+    fs::{self, File, Metadata},
+    io,
+    path::{Component, Path},
+    time::Instant,
+};
+use thiserror::Error;
 
-fn propagate(cwd: &Vec<String>, cwd_size: usize, dir_sizes: &mut HashMap<String, usize>) {
-    let mut key: String = "/".into();
-    if dir_sizes.contains_key(&key) {
-        *dir_sizes.get_mut(&key).unwrap() += cwd_size;
-    } else {
-        dir_sizes.insert(key.clone(), cwd_size);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct NodeId(usize);
+
+#[derive(Debug)]
+enum NodeKind {
+    Dir(Vec<NodeId>),
+    File(usize),
+}
+
+#[derive(Debug)]
+struct Node {
+    name: String,
+    parent: Option<NodeId>,
+    kind: NodeKind,
+    size: OnceCell<usize>,
+}
+
+/// An arena-backed filesystem tree. Directory sizes are computed on first
+/// use and cached; adding a node clears the caches above it.
+#[derive(Debug)]
+struct FsTree {
+    nodes: Vec<Node>,
+}
+
+impl FsTree {
+    fn new() -> Self {
+        FsTree {
+            nodes: vec![Node {
+                name: String::new(),
+                parent: None,
+                kind: NodeKind::Dir(vec![]),
+                size: OnceCell::new(),
+            }],
+        }
+    }
+
+    fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    fn name(&self, id: NodeId) -> &str {
+        &self.nodes[id.0].name
+    }
+
+    fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+
+    fn is_dir(&self, id: NodeId) -> bool {
+        matches!(self.nodes[id.0].kind, NodeKind::Dir(_))
+    }
+
+    fn children(&self, id: NodeId) -> &[NodeId] {
+        match &self.nodes[id.0].kind {
+            NodeKind::Dir(children) => children,
+            NodeKind::File(_) => &[],
+        }
+    }
+
+    fn child(&self, id: NodeId, name: &str) -> Option<NodeId> {
+        self.children(id)
+            .iter()
+            .copied()
+            .find(|c| self.name(*c) == name)
+    }
+
+    fn add(&mut self, parent: NodeId, name: &str, kind: NodeKind) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+            name: name.to_owned(),
+            parent: Some(parent),
+            kind,
+            size: OnceCell::new(),
+        });
+        match &mut self.nodes[parent.0].kind {
+            NodeKind::Dir(children) => children.push(id),
+            NodeKind::File(_) => panic!("{} is not a directory", self.path(parent)),
+        }
+        // A cached size implies cached sizes below it, so stop at the first
+        // ancestor without one.
+        let mut ancestor = Some(parent);
+        while let Some(a) = ancestor {
+            if self.nodes[a.0].size.take().is_none() {
+                break;
+            }
+            ancestor = self.parent(a);
+        }
+        id
     }
-    for part in cwd {
-        key = key + "/" + part;
-        if dir_sizes.contains_key(&key) {
-            *dir_sizes.get_mut(&key).unwrap() += cwd_size;
+
+    /// Returns the existing child directory called `name`, creating it if needed.
+    fn add_dir(&mut self, parent: NodeId, name: &str) -> NodeId {
+        match self.child(parent, name) {
+            Some(id) => id,
+            None => self.add(parent, name, NodeKind::Dir(vec![])),
+        }
+    }
+
+    /// Returns the existing child file called `name`, creating it if needed.
+    fn add_file(&mut self, parent: NodeId, name: &str, size: usize) -> NodeId {
+        match self.child(parent, name) {
+            Some(id) => id,
+            None => self.add(parent, name, NodeKind::File(size)),
+        }
+    }
+
+    /// The size of a file, or the total size of everything below a directory.
+    fn size(&self, id: NodeId) -> usize {
+        let node = &self.nodes[id.0];
+        *node.size.get_or_init(|| match &node.kind {
+            NodeKind::File(size) => *size,
+            NodeKind::Dir(children) => children.iter().map(|c| self.size(*c)).sum(),
+        })
+    }
+
+    fn path(&self, id: NodeId) -> String {
+        match self.parent(id) {
+            None => "/".into(),
+            Some(parent) if parent == self.root() => format!("/{}", self.name(id)),
+            Some(parent) => format!("{}/{}", self.path(parent), self.name(id)),
+        }
+    }
+
+    /// All nodes below `id` (inclusive) in depth-first order, with their depth.
+    fn walk(&self, id: NodeId) -> Vec<(NodeId, usize)> {
+        let mut out = vec![];
+        let mut stack = vec![(id, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            out.push((id, depth));
+            for child in self.children(id).iter().rev() {
+                stack.push((*child, depth + 1));
+            }
+        }
+        out
+    }
+
+    fn dirs(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.walk(self.root())
+            .into_iter()
+            .map(|(id, _)| id)
+            .filter(|id| self.is_dir(*id))
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+enum TranscriptError {
+    #[error("line {line}: malformed line `{text}`")]
+    Malformed { line: usize, text: String },
+    #[error("line {line}: unknown command `{command}`")]
+    UnknownCommand { line: usize, command: String },
+    #[error("line {line}: `ls` output without a preceding `ls`")]
+    UnexpectedOutput { line: usize },
+    #[error("line {line}: `cd ..` from the root directory")]
+    AboveRoot { line: usize },
+    #[error("line {line}: cannot `cd` into file {path}")]
+    NotADirectory { line: usize, path: String },
+    #[error("line {line}: {path} was already listed differently")]
+    Conflict { line: usize, path: String },
+    #[error("line {line}: {path} is listed again")]
+    Relisted { line: usize, path: String },
+    #[error("line {line}: {path} is listed but its parent never mentioned it")]
+    UnknownDirectory { line: usize, path: String },
+    #[error("line {line}: `{name}` is not a valid file name")]
+    BadName { line: usize, name: String },
+}
+
+/// Whether `name` is a single ordinary path component, so it cannot reach
+/// outside its parent directory.
+fn is_plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(c)), None) if c == name
+    )
+}
+
+/// Replays a shell transcript into a tree. Repeated `ls` output is merged
+/// rather than counted twice; with `strict`, re-listing a directory or
+/// listing one that was never seen in its parent's listing is an error.
+fn parse_transcript(input: &str, strict: bool) -> Result<FsTree, TranscriptError> {
+    let mut tree = FsTree::new();
+    let mut cwd = tree.root();
+    let mut listing = false;
+    let mut listed: HashSet<NodeId> = HashSet::new();
+    let mut known: HashSet<NodeId> = HashSet::from([tree.root()]);
+    for (idx, text) in input.lines().enumerate() {
+        let line = idx + 1;
+        let malformed = || TranscriptError::Malformed {
+            line,
+            text: text.to_owned(),
+        };
+        let check_name = |name: &str| {
+            if is_plain_name(name) {
+                Ok(())
+            } else {
+                Err(TranscriptError::BadName {
+                    line,
+                    name: name.to_owned(),
+                })
+            }
+        };
+        // Names run to the end of the line and may contain spaces.
+        let parts: Vec<&str> = if text.starts_with('$') {
+            text.splitn(3, ' ').collect()
         } else {
-            dir_sizes.insert(key.clone(), cwd_size);
-        }
-    }
-}
-
-fn parse(input: &str) -> HashMap<String, usize> {
-    let mut dir_sizes = HashMap::<String, usize>::new();
-    let mut cwd: Vec<String> = vec![];
-    let mut cwd_size: usize = 0;
-    for line in input.lines() {
-        match line.chars().next().unwrap() {
-            '$' => {
-                propagate(&cwd, cwd_size, &mut dir_sizes);
-                cwd_size = 0;
-                if line.starts_with("$ cd") {
-                    let dir = line.split_whitespace().last().unwrap();
-                    if dir == "/" {
-                        cwd.clear();
-                    } else if dir == ".." {
-                        cwd.pop().unwrap();
-                    } else {
-                        cwd.push(dir.to_owned());
+            text.splitn(2, ' ').collect()
+        };
+        match parts[..] {
+            ["$", "cd", dir] => {
+                listing = false;
+                cwd = match dir {
+                    "/" => tree.root(),
+                    ".." => tree
+                        .parent(cwd)
+                        .ok_or(TranscriptError::AboveRoot { line })?,
+                    _ => match tree.child(cwd, dir) {
+                        None if !is_plain_name(dir) => {
+                            return Err(TranscriptError::BadName {
+                                line,
+                                name: dir.to_owned(),
+                            })
+                        }
+                        Some(id) if !tree.is_dir(id) => {
+                            return Err(TranscriptError::NotADirectory {
+                                line,
+                                path: tree.path(id),
+                            })
+                        }
+                        Some(id) => id,
+                        None => tree.add_dir(cwd, dir),
+                    },
+                };
+            }
+            ["$", "ls"] => {
+                let path = || tree.path(cwd);
+                if !listed.insert(cwd) && strict {
+                    return Err(TranscriptError::Relisted { line, path: path() });
+                }
+                if !known.contains(&cwd) && strict {
+                    return Err(TranscriptError::UnknownDirectory { line, path: path() });
+                }
+                listing = true;
+            }
+            ["$", command, ..] => {
+                return Err(TranscriptError::UnknownCommand {
+                    line,
+                    command: command.to_owned(),
+                })
+            }
+            [_, _] if !listing => return Err(TranscriptError::UnexpectedOutput { line }),
+            ["dir", name] => {
+                check_name(name)?;
+                let id = match tree.child(cwd, name) {
+                    Some(id) if tree.is_dir(id) => id,
+                    Some(id) => {
+                        return Err(TranscriptError::Conflict {
+                            line,
+                            path: tree.path(id),
+                        })
+                    }
+                    None => tree.add_dir(cwd, name),
+                };
+                known.insert(id);
+            }
+            [size, name] => {
+                let size = size.parse::<usize>().map_err(|_| malformed())?;
+                check_name(name)?;
+                match tree.child(cwd, name) {
+                    Some(id) if !tree.is_dir(id) && tree.size(id) == size => {}
+                    Some(id) => {
+                        return Err(TranscriptError::Conflict {
+                            line,
+                            path: tree.path(id),
+                        })
+                    }
+                    None => {
+                        tree.add_file(cwd, name, size);
                     }
-                } else if line.starts_with("$ ls") {
-                    // Nothing to do.
-                } else {
-                    unreachable!();
                 }
             }
-            'd' => {}
-            '0'..='9' => {
-                cwd_size += line
-                    .split_whitespace()
-                    .next()
-                    .unwrap()
-                    .parse::<usize>()
-                    .unwrap();
+            _ => return Err(malformed()),
+        }
+    }
+    Ok(tree)
+}
+
+fn parse(input: &str) -> Result<FsTree, TranscriptError> {
+    parse_transcript(input, false)
+}
+
+/// Checks that a transcript lists every directory exactly once, and only
+/// after seeing it in its parent's listing.
+fn validate(input: &str) -> Result<(), TranscriptError> {
+    parse_transcript(input, true).map(|_| ())
+}
+
+fn part1(tree: &FsTree) -> usize {
+    tree.dirs()
+        .map(|id| tree.size(id))
+        .filter(|size| *size <= 100000)
+        .sum()
+}
+
+/// The disk's capacity and the free space the update needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Disk {
+    size: usize,
+    update: usize,
+}
+
+const PUZZLE_DISK: Disk = Disk {
+    size: 70000000,
+    update: 30000000,
+};
+
+/// How much must be deleted to make room for the update; zero if there is
+/// already enough free space.
+fn space_needed(tree: &FsTree, disk: &Disk) -> usize {
+    (tree.size(tree.root()) + disk.update).saturating_sub(disk.size)
+}
+
+/// Directories big enough on their own to free the space needed, smallest
+/// first.
+fn deletion_candidates(tree: &FsTree, disk: &Disk) -> Vec<NodeId> {
+    let needed = space_needed(tree, disk);
+    let mut candidates: Vec<NodeId> = tree.dirs().filter(|id| tree.size(*id) >= needed).collect();
+    candidates.sort_by_key(|id| tree.size(*id));
+    candidates
+}
+
+/// The size of the smallest directory whose deletion frees enough space, or
+/// zero if nothing needs deleting. `None` if even deleting everything would
+/// not be enough.
+fn part2(tree: &FsTree, disk: &Disk) -> Option<usize> {
+    if space_needed(tree, disk) == 0 {
+        return Some(0);
+    }
+    deletion_candidates(tree, disk)
+        .first()
+        .map(|id| tree.size(*id))
+}
+
+/// How many branches `minimal_deletion` explores before settling for the
+/// best set found so far.
+const DELETION_SEARCH_LIMIT: usize = 10_000_000;
+
+/// Directories whose deletion frees enough space, and whether the search
+/// proved that no other set frees less.
+#[derive(Debug, PartialEq, Eq)]
+struct Deletion {
+    dirs: Vec<NodeId>,
+    exact: bool,
+}
+
+/// Branch-and-bound over the directories in preorder: each one is either
+/// deleted whole or kept and looked inside. Sets are recorded as indices on
+/// a stack and only copied when they improve on the best so far.
+struct DeletionSearch {
+    sizes: Vec<usize>,
+    /// Just past the end of each directory's subtree.
+    end: Vec<usize>,
+    /// The most that deleting from here onwards could free.
+    reach: Vec<usize>,
+    needed: usize,
+    chosen: Vec<usize>,
+    best: Vec<usize>,
+    best_total: usize,
+    steps_left: usize,
+}
+
+impl DeletionSearch {
+    fn run(&mut self, i: usize, total: usize) {
+        if total >= self.needed {
+            if total < self.best_total {
+                self.best_total = total;
+                self.best = self.chosen.clone();
             }
-            _ => unreachable!(),
+            return;
+        }
+        if i == self.sizes.len()
+            || total + self.reach[i] < self.needed
+            || self.best_total == self.needed
+            || self.steps_left == 0
+        {
+            return;
         }
+        self.steps_left -= 1;
+        if total + self.sizes[i] < self.best_total {
+            self.chosen.push(i);
+            self.run(self.end[i], total + self.sizes[i]);
+            self.chosen.pop();
+        }
+        self.run(i + 1, total);
     }
-    propagate(&cwd, cwd_size, &mut dir_sizes);
-    dir_sizes
 }
 
-fn part1(puzzle: &HashMap<String, usize>) -> usize {
-    puzzle.values().filter(|v| **v <= 100000).sum()
+/// The directories to delete that free enough space while deleting as little
+/// as possible in total. Empty if nothing needs deleting; `None` if it can't
+/// be done. Picking a subset is exponential in the worst case, so the search
+/// starts from `part2`'s single directory and gives up improving on it after
+/// `limit` branches, in which case the result is not `exact`.
+fn minimal_deletion(tree: &FsTree, disk: &Disk, limit: usize) -> Option<Deletion> {
+    let needed = space_needed(tree, disk);
+    if needed == 0 {
+        return Some(Deletion {
+            dirs: vec![],
+            exact: true,
+        });
+    }
+    let (dirs, depths): (Vec<NodeId>, Vec<usize>) = tree
+        .walk(tree.root())
+        .into_iter()
+        .filter(|(id, _)| tree.is_dir(*id))
+        .unzip();
+    let n = dirs.len();
+    let mut end = vec![n; n];
+    let mut open: Vec<usize> = vec![];
+    for (i, depth) in depths.iter().enumerate() {
+        while let Some(&j) = open.last().filter(|&&j| depths[j] >= *depth) {
+            end[j] = i;
+            open.pop();
+        }
+        open.push(i);
+    }
+    let sizes: Vec<usize> = dirs.iter().map(|id| tree.size(*id)).collect();
+    let mut reach = vec![0; n + 1];
+    for i in (0..n).rev() {
+        reach[i] = sizes[i] + reach[end[i]];
+    }
+
+    let first = (0..n)
+        .filter(|i| sizes[*i] >= needed)
+        .min_by_key(|i| sizes[*i])?;
+    let mut search = DeletionSearch {
+        best: vec![first],
+        best_total: sizes[first],
+        sizes,
+        end,
+        reach,
+        needed,
+        chosen: vec![],
+        steps_left: limit,
+    };
+    search.run(0, 0);
+    Some(Deletion {
+        dirs: search.best.iter().map(|i| dirs[*i]).collect(),
+        exact: search.steps_left > 0,
+    })
 }
 
-fn part2(puzzle: &HashMap<String, usize>) -> usize {
-    const FS_SIZE: usize = 70000000;
-    const UPGD_SIZE: usize = 30000000;
-    let fs_used = puzzle.get("/").unwrap();
-    let space_needed = UPGD_SIZE - (FS_SIZE - fs_used);
-    *puzzle
-        .values()
-        .filter(|v| **v >= space_needed)
-        .reduce(|accum, v| if v < accum { v } else { accum })
-        .unwrap()
+/// Formats a size like `du -h`: powers of 1024, rounded up, with one decimal
+/// place below 10.
+fn human_size(size: usize) -> String {
+    const UNITS: [&str; 5] = ["", "K", "M", "G", "T"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        return size.to_string();
+    }
+    // Rounding up can carry into the next width, or the next unit.
+    let tenths = (value * 10.0).ceil() / 10.0;
+    if tenths < 10.0 {
+        return format!("{:.1}{}", tenths, UNITS[unit]);
+    }
+    let whole = value.ceil();
+    if whole >= 1024.0 && unit + 1 < UNITS.len() {
+        format!("1.0{}", UNITS[unit + 1])
+    } else {
+        format!("{:.0}{}", whole, UNITS[unit])
+    }
 }
 
-fn main() {
+/// A `tree`-style drawing of the whole filesystem, with sizes.
+fn report_tree(tree: &FsTree) -> String {
+    fn draw(tree: &FsTree, id: NodeId, prefix: &str, out: &mut String) {
+        let children = tree.children(id);
+        for (i, child) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let (branch, indent) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            *out += &format!(
+                "{}{}{} [{}]\n",
+                prefix,
+                branch,
+                tree.name(*child),
+                tree.size(*child)
+            );
+            draw(tree, *child, &(prefix.to_owned() + indent), out);
+        }
+    }
+
+    let mut out = format!("/ [{}]\n", tree.size(tree.root()));
+    draw(tree, tree.root(), "", &mut out);
+    out
+}
+
+/// A `du -h`-style list of directories, biggest first.
+fn report_du(tree: &FsTree) -> String {
+    let mut dirs: Vec<NodeId> = tree.dirs().collect();
+    dirs.sort_by_key(|id| std::cmp::Reverse(tree.size(*id)));
+    dirs.iter()
+        .map(|id| format!("{}\t{}\n", human_size(tree.size(*id)), tree.path(*id)))
+        .collect()
+}
+
+/// Explains part 2: the space arithmetic and every directory that would do.
+fn report_deletion(tree: &FsTree, disk: &Disk) -> String {
+    let used = tree.size(tree.root());
+    let mut out = format!(
+        "Disk size:  {:>10}\nUsed:       {:>10}\nFree:       {:>10}\nUpdate:     {:>10}\nMust free:  {:>10}\n",
+        disk.size,
+        used,
+        disk.size.saturating_sub(used),
+        disk.update,
+        space_needed(tree, disk)
+    );
+    if space_needed(tree, disk) == 0 {
+        return out + "Nothing needs deleting.\n";
+    }
+    out += "Candidates, smallest first:\n";
+    for (i, id) in deletion_candidates(tree, disk).iter().enumerate() {
+        let note = if i == 0 { "  <- delete this one" } else { "" };
+        out += &format!("  {:>10}  {}{}\n", tree.size(*id), tree.path(*id), note);
+    }
+    if let Some(deletion) = minimal_deletion(tree, disk, DELETION_SEARCH_LIMIT) {
+        let total: usize = deletion.dirs.iter().map(|id| tree.size(*id)).sum();
+        if deletion.exact {
+            out += &format!("Least total to delete: {}\n", total);
+        } else {
+            out += &format!("Least total found (search cut short): {}\n", total);
+        }
+        for id in deletion.dirs {
+            out += &format!("  {:>10}  {}\n", tree.size(id), tree.path(id));
+        }
+    }
+    out
+}
+
+/// Recreates the tree under `dir`, with sparse files of the recorded sizes.
+/// Names that could resolve outside `dir` are refused. `dir` must be new or
+/// empty, so that nothing stale ends up in a later transcript.
+fn materialize(tree: &FsTree, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    if fs::read_dir(dir)?.next().is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} is not empty", dir.display()),
+        ));
+    }
+    for (id, _) in tree.walk(tree.root()).into_iter().skip(1) {
+        let relative = tree.path(id);
+        let relative = Path::new(relative.trim_start_matches('/'));
+        let stays_under = relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if !is_plain_name(tree.name(id)) || !stays_under {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("refusing to create {}", tree.path(id)),
+            ));
+        }
+        let path = dir.join(relative);
+        if tree.is_dir(id) {
+            fs::create_dir_all(path)?;
+        } else {
+            File::create(path)?.set_len(tree.size(id) as u64)?;
+        }
+    }
+    Ok(())
+}
+
+/// Walks a real directory and writes the `$ cd`/`$ ls` transcript that
+/// exploring it would produce, visiting entries in name order. Symbolic
+/// links are skipped; names the transcript cannot hold (not UTF-8, or with
+/// a line break) are an error.
+fn transcribe(dir: &Path) -> io::Result<String> {
+    fn visit(dir: &Path, out: &mut String) -> io::Result<()> {
+        let mut entries: Vec<(String, Metadata)> = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let meta = entry.path().symlink_metadata()?;
+            if !meta.file_type().is_symlink() {
+                let name = entry
+                    .file_name()
+                    .into_string()
+                    .ok()
+                    .filter(|name| !name.contains(['\n', '\r']))
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("cannot transcribe {}", entry.path().display()),
+                        )
+                    })?;
+                entries.push((name, meta));
+            }
+        }
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        *out += "$ ls\n";
+        for (name, meta) in &entries {
+            if meta.is_dir() {
+                *out += &format!("dir {}\n", name);
+            } else {
+                *out += &format!("{} {}\n", meta.len(), name);
+            }
+        }
+        for (name, meta) in &entries {
+            if meta.is_dir() {
+                *out += &format!("$ cd {}\n", name);
+                visit(&dir.join(name), out)?;
+                *out += "$ cd ..\n";
+            }
+        }
+        Ok(())
+    }
+
+    let mut out = String::from("$ cd /\n");
+    visit(dir, &mut out)?;
+    Ok(out)
+}
+
+fn main() -> Result<()> {
     let input = include_str!("../../input/07.txt");
-    let parsed = parse(input);
+    let parsed = parse(input)?;
 
     let timer = Instant::now();
     let p1 = part1(&parsed);
     println!("Part 1: {:?}\n(elapsed: {:.2?})", p1, timer.elapsed());
 
+    let args: Vec<String> = std::env::args().collect();
+    let option = |name: &str| -> Result<Option<usize>> {
+        match args.iter().skip_while(|a| *a != name).nth(1) {
+            Some(value) => Ok(Some(value.parse()?)),
+            None => Ok(None),
+        }
+    };
+    let disk = Disk {
+        size: option("--disk")?.unwrap_or(PUZZLE_DISK.size),
+        update: option("--update")?.unwrap_or(PUZZLE_DISK.update),
+    };
+
     let timer = Instant::now();
-    let p2 = part2(&parsed);
+    let p2 =
+        part2(&parsed, &disk).ok_or_else(|| anyhow!("the disk is too small for the update"))?;
     println!("Part 2: {:?}\n(elapsed: {:.2?})", p2, timer.elapsed());
+
+    if args.iter().any(|a| a == "--validate") {
+        validate(input)?;
+        println!("Transcript is valid.");
+    }
+    if let Some(dir) = args.iter().skip_while(|a| *a != "--materialize").nth(1) {
+        materialize(&parsed, Path::new(dir))?;
+    }
+    if let Some(dir) = args.iter().skip_while(|a| *a != "--transcribe").nth(1) {
+        print!("{}", transcribe(Path::new(dir))?);
+    }
+    if args.iter().any(|a| a == "--tree") {
+        print!("{}", report_tree(&parsed));
+    }
+    if args.iter().any(|a| a == "--du") {
+        print!("{}", report_du(&parsed));
+    }
+    if args.iter().any(|a| a == "--explain") {
+        print!("{}", report_deletion(&parsed, &disk));
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{Deletion, Disk, FsTree, TranscriptError, DELETION_SEARCH_LIMIT, PUZZLE_DISK};
+    use proptest::prelude::*;
+    use std::{collections::BTreeMap, path::PathBuf};
+
     const SAMPLE: &str = r"$ cd /
 $ ls
 dir a
@@ -128,16 +730,423 @@ $ ls
 5626152 d.ext
 7214296 k";
 
+    #[test]
+    fn sizes_follow_changes() {
+        let mut tree = super::parse(SAMPLE).unwrap();
+        let e = tree
+            .child(tree.child(tree.root(), "a").unwrap(), "e")
+            .unwrap();
+        assert_eq!(48381165, tree.size(tree.root()));
+        tree.add_file(e, "j", 1000);
+        assert_eq!(1584, tree.size(e));
+        assert_eq!(48382165, tree.size(tree.root()));
+    }
+
     #[test]
     fn part1_works() {
-        let input = super::parse(SAMPLE);
-        println!("{:?}", input);
+        let input = super::parse(SAMPLE).unwrap();
         assert_eq!(95437, super::part1(&input));
     }
 
     #[test]
     fn part2_works() {
-        let input = super::parse(SAMPLE);
-        assert_eq!(24933642, super::part2(&input));
+        let input = super::parse(SAMPLE).unwrap();
+        assert_eq!(Some(24933642), super::part2(&input, &PUZZLE_DISK));
+    }
+
+    #[test]
+    fn tree_works() {
+        let tree = super::parse(SAMPLE).unwrap();
+        let root = tree.root();
+        let a = tree.child(root, "a").unwrap();
+        let e = tree.child(a, "e").unwrap();
+        assert_eq!("/a/e", tree.path(e));
+        assert_eq!(Some(a), tree.parent(e));
+        assert_eq!(584, tree.size(e));
+        assert_eq!(94853, tree.size(a));
+        assert_eq!(48381165, tree.size(root));
+        let names: Vec<&str> = tree.children(root).iter().map(|c| tree.name(*c)).collect();
+        assert_eq!(vec!["a", "b.txt", "c.dat", "d"], names);
+        assert_eq!(4, tree.dirs().count());
+        assert_eq!(14, tree.walk(root).len());
+        assert_eq!((e, 2), tree.walk(root)[2]);
+    }
+
+    #[test]
+    fn human_size_works() {
+        assert_eq!("584", super::human_size(584));
+        assert_eq!("1.0K", super::human_size(1024));
+        assert_eq!("1.6K", super::human_size(1537));
+        assert_eq!("93K", super::human_size(94853));
+        assert_eq!("10K", super::human_size(10199));
+        assert_eq!("1.0M", super::human_size(1048575));
+        assert_eq!("47M", super::human_size(48381165));
+    }
+
+    #[test]
+    fn report_tree_works() {
+        let tree = super::parse(SAMPLE).unwrap();
+        assert_eq!(
+            r"/ [48381165]
+├── a [94853]
+│   ├── e [584]
+│   │   └── i [584]
+│   ├── f [29116]
+│   ├── g [2557]
+│   └── h.lst [62596]
+├── b.txt [14848514]
+├── c.dat [8504156]
+└── d [24933642]
+    ├── j [4060174]
+    ├── d.log [8033020]
+    ├── d.ext [5626152]
+    └── k [7214296]
+",
+            super::report_tree(&tree)
+        );
+    }
+
+    #[test]
+    fn report_du_works() {
+        let tree = super::parse(SAMPLE).unwrap();
+        assert_eq!(
+            "47M\t/\n24M\t/d\n93K\t/a\n584\t/a/e\n",
+            super::report_du(&tree)
+        );
+    }
+
+    #[test]
+    fn report_deletion_works() {
+        let tree = super::parse(SAMPLE).unwrap();
+        let report = super::report_deletion(&tree, &PUZZLE_DISK);
+        assert!(report.contains("Must free:     8381165\n"));
+        assert!(report.contains("    24933642  /d  <- delete this one\n    48381165  /\n"));
+        assert!(report.ends_with("Least total to delete: 24933642\n    24933642  /d\n"));
+    }
+
+    #[test]
+    fn no_deletion_needed() {
+        let tree = super::parse(SAMPLE).unwrap();
+        let disk = Disk {
+            size: 100000000,
+            update: 30000000,
+        };
+        assert_eq!(0, super::space_needed(&tree, &disk));
+        assert_eq!(Some(0), super::part2(&tree, &disk));
+        assert_eq!(
+            Some(Deletion {
+                dirs: vec![],
+                exact: true
+            }),
+            super::minimal_deletion(&tree, &disk, DELETION_SEARCH_LIMIT)
+        );
+        assert!(super::report_deletion(&tree, &disk).ends_with("Nothing needs deleting.\n"));
+    }
+
+    #[test]
+    fn impossible_deletion() {
+        let tree = super::parse(SAMPLE).unwrap();
+        let disk = Disk {
+            size: 40000000,
+            update: 50000000,
+        };
+        assert_eq!(None, super::part2(&tree, &disk));
+        assert_eq!(
+            None,
+            super::minimal_deletion(&tree, &disk, DELETION_SEARCH_LIMIT)
+        );
+    }
+
+    #[test]
+    fn minimal_deletion_works() {
+        let tree = super::parse(SAMPLE).unwrap();
+        let deletion = super::minimal_deletion(&tree, &PUZZLE_DISK, DELETION_SEARCH_LIMIT).unwrap();
+        assert!(deletion.exact);
+        let paths: Vec<String> = deletion.dirs.iter().map(|id| tree.path(*id)).collect();
+        assert_eq!(vec!["/d"], paths);
+
+        // Two small directories beat the one big enough on its own.
+        let tree = super::parse(
+            "$ cd /\n$ ls\ndir x\ndir y\ndir z\n$ cd x\n$ ls\n6 f\n$ cd ..\n$ cd y\n$ ls\n6 f\n$ cd ..\n$ cd z\n$ ls\n20 f",
+        )
+        .unwrap();
+        let disk = Disk {
+            size: 32,
+            update: 10,
+        };
+        let deletion = super::minimal_deletion(&tree, &disk, DELETION_SEARCH_LIMIT).unwrap();
+        assert!(deletion.exact);
+        let paths: Vec<String> = deletion.dirs.iter().map(|id| tree.path(*id)).collect();
+        assert_eq!(vec!["/x", "/y"], paths);
+        assert_eq!(Some(20), super::part2(&tree, &disk));
+    }
+
+    #[test]
+    fn sample_is_valid() {
+        assert_eq!(Ok(()), super::validate(SAMPLE));
+    }
+
+    #[test]
+    fn relisting_is_deduplicated() {
+        let input = format!("{}\n$ cd /\n$ ls\n14848514 b.txt\ndir a", SAMPLE);
+        let tree = super::parse(&input).unwrap();
+        assert_eq!(48381165, tree.size(tree.root()));
+        assert_eq!(
+            Err(TranscriptError::Relisted {
+                line: 25,
+                path: "/".into()
+            }),
+            super::validate(&input)
+        );
+    }
+
+    #[test]
+    fn unknown_directory_is_reported() {
+        let input = "$ cd /\n$ ls\ndir a\n$ cd b\n$ ls\n1 x";
+        assert!(super::parse(input).is_ok());
+        assert_eq!(
+            Err(TranscriptError::UnknownDirectory {
+                line: 5,
+                path: "/b".into()
+            }),
+            super::validate(input)
+        );
+    }
+
+    #[test]
+    fn navigation_errors_are_reported() {
+        assert_eq!(
+            TranscriptError::AboveRoot { line: 2 },
+            super::parse("$ cd /\n$ cd ..").unwrap_err()
+        );
+        assert_eq!(
+            TranscriptError::NotADirectory {
+                line: 4,
+                path: "/f".into()
+            },
+            super::parse("$ cd /\n$ ls\n12 f\n$ cd f").unwrap_err()
+        );
+        assert_eq!(
+            TranscriptError::UnknownCommand {
+                line: 2,
+                command: "rm".into()
+            },
+            super::parse("$ cd /\n$ rm -rf a").unwrap_err()
+        );
+        assert_eq!(
+            TranscriptError::UnexpectedOutput { line: 2 },
+            super::parse("$ cd /\n12 f").unwrap_err()
+        );
+        assert_eq!(
+            TranscriptError::Malformed {
+                line: 3,
+                text: "twelve f".into()
+            },
+            super::parse("$ cd /\n$ ls\ntwelve f").unwrap_err()
+        );
+        assert_eq!(
+            TranscriptError::Conflict {
+                line: 5,
+                path: "/f".into()
+            },
+            super::parse("$ cd /\n$ ls\n12 f\n$ ls\n13 f").unwrap_err()
+        );
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("day07-{}-{}", name, std::process::id()))
+    }
+
+    fn sizes_by_path(tree: &FsTree) -> BTreeMap<String, (bool, usize)> {
+        tree.walk(tree.root())
+            .into_iter()
+            .map(|(id, _)| (tree.path(id), (tree.is_dir(id), tree.size(id))))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip_works() {
+        let mut tree = super::parse(SAMPLE).unwrap();
+        let spaced = tree.add_dir(tree.root(), "my docs");
+        tree.add_file(spaced, "read me.txt", 42);
+        let dir = scratch_dir("sample");
+        super::materialize(&tree, &dir).unwrap();
+        assert_eq!(584, std::fs::metadata(dir.join("a/e/i")).unwrap().len());
+        let transcript = super::transcribe(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(transcript.starts_with("$ cd /\n$ ls\ndir a\n14848514 b.txt\n"));
+        assert_eq!(Ok(()), super::validate(&transcript));
+        let copy = super::parse(&transcript).unwrap();
+        assert_eq!(sizes_by_path(&tree), sizes_by_path(&copy));
+        assert!(transcript.contains("$ cd my docs\n$ ls\n42 read me.txt\n"));
+    }
+
+    #[test]
+    fn materialize_needs_an_empty_directory() {
+        let tree = super::parse(SAMPLE).unwrap();
+        let dir = scratch_dir("stale");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("stale.txt"), "old").unwrap();
+        let err = super::materialize(&tree, &dir).unwrap_err();
+        let untouched = !dir.join("a").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(std::io::ErrorKind::AlreadyExists, err.kind());
+        assert!(untouched);
+    }
+
+    #[test]
+    fn unrepresentable_names_are_refused() {
+        let dir = scratch_dir("newline");
+        std::fs::create_dir_all(dir.join("two\nlines")).unwrap();
+        let err = super::transcribe(&dir).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn escaping_names_are_refused() {
+        for (text, name) in [
+            ("$ cd /\n$ ls\n5 ../victim", "../victim"),
+            ("$ cd /\n$ ls\ndir ..", ".."),
+            ("$ cd /\n$ ls\n5 .", "."),
+            ("$ cd /\n$ cd a/b", "a/b"),
+        ] {
+            assert_eq!(
+                TranscriptError::BadName {
+                    line: text.lines().count(),
+                    name: name.into()
+                },
+                super::parse(text).unwrap_err()
+            );
+        }
+
+        let mut tree = FsTree::new();
+        let root = tree.root();
+        tree.add_file(root, "../victim", 5);
+        let dir = scratch_dir("escape");
+        let err = super::materialize(&tree, &dir).unwrap_err();
+        let escaped = dir.parent().unwrap().join("victim").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(std::io::ErrorKind::InvalidInput, err.kind());
+        assert!(!escaped);
+    }
+
+    /// Builds a tree from (parent, is_dir, size) triples, where `parent`
+    /// picks one of the directories created so far.
+    fn build_tree(entries: &[(usize, bool, u32)]) -> FsTree {
+        let mut tree = FsTree::new();
+        let mut dirs = vec![tree.root()];
+        for (i, (parent, is_dir, size)) in entries.iter().enumerate() {
+            let parent = dirs[parent % dirs.len()];
+            let name = format!("n{}", i);
+            if *is_dir {
+                dirs.push(tree.add_dir(parent, &name));
+            } else {
+                tree.add_file(parent, &name, *size as usize);
+            }
+        }
+        tree
+    }
+
+    #[test]
+    fn minimal_deletion_scales() {
+        // Roughly the shape of a real input: about 200 directories holding
+        // around 45MB, with a few small files each.
+        let mut seed: u64 = 7;
+        let mut next = |bound: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % bound
+        };
+        let entries: Vec<(usize, bool, u32)> = (0..1000)
+            .map(|i| {
+                let is_dir = i % 5 == 0;
+                (next(1000) as usize, is_dir, next(112_000) as u32 + 1)
+            })
+            .collect();
+        let tree = build_tree(&entries);
+        assert!(tree.dirs().count() > 190);
+        let used = tree.size(tree.root());
+        assert!((40_000_000..50_000_000).contains(&used));
+        let disk = Disk {
+            size: used + 40_000_000 - 5_000_000,
+            update: 40_000_000,
+        };
+        // Too many near-misses to rule out, so the search stops at its limit
+        // but still improves on the best single directory.
+        let deletion = super::minimal_deletion(&tree, &disk, DELETION_SEARCH_LIMIT).unwrap();
+        assert!(!deletion.exact);
+        let total: usize = deletion.dirs.iter().map(|id| tree.size(*id)).sum();
+        assert!(total >= 5_000_000);
+        assert!(total < super::part2(&tree, &disk).unwrap());
+    }
+
+    /// The least total over every set of directories with none inside
+    /// another, found by trying them all.
+    fn brute_force_deletion(tree: &FsTree, needed: usize) -> Option<usize> {
+        let dirs: Vec<_> = tree.dirs().collect();
+        let inside = |a, b| {
+            let mut id = Some(a);
+            while let Some(i) = id {
+                if i == b {
+                    return true;
+                }
+                id = tree.parent(i);
+            }
+            false
+        };
+        (0..1u32 << dirs.len())
+            .map(|mask| {
+                (0..dirs.len())
+                    .filter(|i| mask & (1 << i) != 0)
+                    .map(|i| dirs[i])
+                    .collect::<Vec<_>>()
+            })
+            .filter(|set| {
+                set.iter()
+                    .all(|a| set.iter().all(|b| a == b || !inside(*a, *b)))
+            })
+            .map(|set| set.iter().map(|id| tree.size(*id)).sum::<usize>())
+            .filter(|total| *total >= needed)
+            .min()
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn generated_trees_round_trip(
+            entries in prop::collection::vec((0..100usize, any::<bool>(), 0..1_000_000u32), 0..30)
+        ) {
+            let tree = build_tree(&entries);
+            let dir = scratch_dir("generated");
+            super::materialize(&tree, &dir).unwrap();
+            let transcript = super::transcribe(&dir).unwrap();
+            std::fs::remove_dir_all(&dir).unwrap();
+            prop_assert_eq!(Ok(()), super::validate(&transcript));
+            let copy = super::parse(&transcript).unwrap();
+            prop_assert_eq!(sizes_by_path(&tree), sizes_by_path(&copy));
+        }
+
+        #[test]
+        fn minimal_deletion_matches_brute_force(
+            entries in prop::collection::vec((0..100usize, any::<bool>(), 0..1000u32), 0..24),
+            update in 0..20_000usize,
+        ) {
+            let tree = build_tree(&entries);
+            prop_assume!(tree.dirs().count() <= 12);
+            let disk = Disk { size: 10_000, update };
+            let needed = super::space_needed(&tree, &disk);
+            let total = match super::minimal_deletion(&tree, &disk, DELETION_SEARCH_LIMIT) {
+                Some(deletion) => {
+                    prop_assert!(deletion.exact);
+                    Some(deletion.dirs.iter().map(|id| tree.size(*id)).sum::<usize>())
+                }
+                None => None,
+            };
+            let expected = if needed == 0 { Some(0) } else { brute_force_deletion(&tree, needed) };
+            prop_assert_eq!(expected, total);
+        }
     }
 }
@@ -16,7 +16,11 @@
 
 use std::time::Instant;
 
-fn is_visible(puzzle: &Vec<Vec<u8>>, row: usize, col: usize) -> bool {
+use anyhow::Result;
+use aoc22::render::{ansi_bg, ansi_fg, elevation, Image, ANSI_RESET};
+use thiserror::Error;
+
+fn is_visible(puzzle: &[Vec<u8>], row: usize, col: usize) -> bool {
     if row == 0 || row == puzzle.len() - 1 {
         return true;
     }
@@ -47,8 +51,8 @@ fn is_visible(puzzle: &Vec<Vec<u8>>, row: usize, col: usize) -> bool {
     }
 
     visible = true;
-    for c in 0..col {
-        if puzzle[row][c] >= target {
+    for h in puzzle[row].iter().take(col) {
+        if *h >= target {
             visible = false;
         }
     }
@@ -57,8 +61,8 @@ fn is_visible(puzzle: &Vec<Vec<u8>>, row: usize, col: usize) -> bool {
     }
 
     visible = true;
-    for c in col + 1..puzzle[row].len() {
-        if puzzle[row][c] >= target {
+    for h in puzzle[row].iter().skip(col + 1) {
+        if *h >= target {
             visible = false;
         }
     }
@@ -69,42 +73,197 @@ fn is_visible(puzzle: &Vec<Vec<u8>>, row: usize, col: usize) -> bool {
     false
 }
 
-fn viewing_score(puzzle: &[Vec<u8>], row: usize, col: usize) -> usize {
-    let target = puzzle[row][col];
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    North,
+    South,
+    West,
+    East,
+}
 
-    let mut left = 0;
-    for r in (0..row).rev() {
-        left += 1;
-        if puzzle[r][col] >= target {
-            break;
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+    ];
+
+    /// The (row, col) position `distance` trees away, if it is on the map.
+    fn step(self, row: usize, col: usize, distance: usize) -> Option<(usize, usize)> {
+        match self {
+            Direction::North => Some((row.checked_sub(distance)?, col)),
+            Direction::South => Some((row + distance, col)),
+            Direction::West => Some((row, col.checked_sub(distance)?)),
+            Direction::East => Some((row, col + distance)),
         }
     }
+}
 
-    let mut right = 0;
-    for p_row in puzzle.iter().skip(row + 1) {
-        right += 1;
-        if p_row[col] >= target {
+/// How many trees the tree at (`row`, `col`) can see looking in `dir`,
+/// stopping at the first tree at least as tall as itself.
+fn viewing_distance(puzzle: &[Vec<u8>], row: usize, col: usize, dir: Direction) -> usize {
+    let target = puzzle[row][col];
+    let line: Box<dyn Iterator<Item = u8>> = match dir {
+        Direction::North => Box::new(puzzle[..row].iter().rev().map(|r| r[col])),
+        Direction::South => Box::new(puzzle[row + 1..].iter().map(|r| r[col])),
+        Direction::West => Box::new(puzzle[row][..col].iter().rev().copied()),
+        Direction::East => Box::new(puzzle[row][col + 1..].iter().copied()),
+    };
+    let mut distance = 0;
+    for h in line {
+        distance += 1;
+        if h >= target {
             break;
         }
     }
+    distance
+}
 
-    let mut top = 0;
-    for c in (0..col).rev() {
-        top += 1;
-        if puzzle[row][c] >= target {
-            break;
+fn viewing_score(puzzle: &[Vec<u8>], row: usize, col: usize) -> usize {
+    Direction::ALL
+        .iter()
+        .map(|dir| viewing_distance(puzzle, row, col, *dir))
+        .product()
+}
+
+/// Visibility and per-direction viewing distances for every tree, computed
+/// in linear passes.
+struct Survey {
+    visible: Vec<Vec<bool>>,
+    distances: Vec<Vec<[usize; 4]>>,
+}
+
+impl Survey {
+    fn viewing_distance(&self, row: usize, col: usize, dir: Direction) -> usize {
+        self.distances[row][col][dir as usize]
+    }
+
+    fn viewing_score(&self, row: usize, col: usize) -> usize {
+        Direction::ALL
+            .iter()
+            .map(|dir| self.viewing_distance(row, col, *dir))
+            .product()
+    }
+}
+
+/// Walks one line of trees, marking those visible from the start of the line
+/// and recording how far each one can see back towards the start, which is
+/// the direction `looking`.
+///
+/// The stack holds the positions of trees that could still block the view of
+/// a later tree; their heights never increase from bottom to top, so each
+/// tree is pushed and popped at most once.
+fn scan_line(puzzle: &[Vec<u8>], line: &[(usize, usize)], looking: Direction, survey: &mut Survey) {
+    let mut tallest: Option<u8> = None;
+    let mut stack: Vec<usize> = vec![];
+    for (i, &(row, col)) in line.iter().enumerate() {
+        let target = puzzle[row][col];
+        if tallest.is_none_or(|t| target > t) {
+            survey.visible[row][col] = true;
+            tallest = Some(target);
+        }
+        while let Some(&top) = stack.last() {
+            let (r, c) = line[top];
+            if puzzle[r][c] >= target {
+                break;
+            }
+            stack.pop();
         }
+        survey.distances[row][col][looking as usize] = stack.last().map_or(i, |top| i - top);
+        stack.push(i);
     }
+}
 
-    let mut bottom = 0;
-    for c in col + 1..puzzle[row].len() {
-        bottom += 1;
-        if puzzle[row][c] >= target {
-            break;
+fn survey(puzzle: &[Vec<u8>]) -> Survey {
+    let rows = puzzle.len();
+    let cols = puzzle.first().map_or(0, |r| r.len());
+    let mut survey = Survey {
+        visible: vec![vec![false; cols]; rows],
+        distances: vec![vec![[0; 4]; cols]; rows],
+    };
+    for row in 0..rows {
+        let mut line: Vec<(usize, usize)> = (0..cols).map(|col| (row, col)).collect();
+        scan_line(puzzle, &line, Direction::West, &mut survey);
+        line.reverse();
+        scan_line(puzzle, &line, Direction::East, &mut survey);
+    }
+    for col in 0..cols {
+        let mut line: Vec<(usize, usize)> = (0..rows).map(|row| (row, col)).collect();
+        scan_line(puzzle, &line, Direction::North, &mut survey);
+        line.reverse();
+        scan_line(puzzle, &line, Direction::South, &mut survey);
+    }
+    survey
+}
+
+/// A height field with any number of dimensions, stored row-major, so that
+/// visibility and scenic score can be asked of (for example) 3D volumes.
+struct Volume {
+    shape: Vec<usize>,
+    heights: Vec<u8>,
+}
+
+impl Volume {
+    fn from_grid(puzzle: &[Vec<u8>]) -> Self {
+        Volume {
+            shape: vec![puzzle.len(), puzzle.first().map_or(0, |r| r.len())],
+            heights: puzzle.iter().flatten().copied().collect(),
         }
     }
 
-    left * right * top * bottom
+    fn index(&self, point: &[usize]) -> usize {
+        point
+            .iter()
+            .zip(&self.shape)
+            .fold(0, |acc, (p, len)| acc * len + p)
+    }
+
+    fn points(&self) -> impl Iterator<Item = Vec<usize>> + '_ {
+        (0..self.heights.len()).map(|mut i| {
+            let mut point = vec![0; self.shape.len()];
+            for (p, len) in point.iter_mut().zip(&self.shape).rev() {
+                *p = i % len;
+                i /= len;
+            }
+            point
+        })
+    }
+
+    /// Looks from `point` along `axis` (towards higher coordinates if
+    /// `forward`), returning how many trees are seen and whether the view
+    /// reaches the edge unblocked.
+    fn sight(&self, point: &[usize], axis: usize, forward: bool) -> (usize, bool) {
+        let target = self.heights[self.index(point)];
+        let mut p = point.to_vec();
+        let mut distance = 0;
+        loop {
+            if forward && p[axis] + 1 < self.shape[axis] {
+                p[axis] += 1;
+            } else if !forward && p[axis] > 0 {
+                p[axis] -= 1;
+            } else {
+                return (distance, true);
+            }
+            distance += 1;
+            if self.heights[self.index(&p)] >= target {
+                return (distance, false);
+            }
+        }
+    }
+
+    fn sights<'a>(&'a self, point: &'a [usize]) -> impl Iterator<Item = (usize, bool)> + 'a {
+        (0..self.shape.len())
+            .flat_map(move |axis| [false, true].map(|forward| self.sight(point, axis, forward)))
+    }
+
+    fn is_visible(&self, point: &[usize]) -> bool {
+        self.sights(point).any(|(_, edge)| edge)
+    }
+
+    fn viewing_score(&self, point: &[usize]) -> usize {
+        self.sights(point).map(|(distance, _)| distance).product()
+    }
 }
 
 fn parse(input: &str) -> Vec<Vec<u8>> {
@@ -119,7 +278,167 @@ fn parse(input: &str) -> Vec<Vec<u8>> {
     forest
 }
 
-fn part1(puzzle: &Vec<Vec<u8>>) -> usize {
+fn part1(puzzle: &[Vec<u8>]) -> usize {
+    survey(puzzle)
+        .visible
+        .iter()
+        .flatten()
+        .filter(|v| **v)
+        .count()
+}
+
+/// The tree with the highest scenic score.
+fn best_spot(puzzle: &[Vec<u8>], survey: &Survey) -> Option<(usize, usize)> {
+    let cols = puzzle.first().map_or(0, |r| r.len());
+    (0..puzzle.len())
+        .flat_map(|row| (0..cols).map(move |col| (row, col)))
+        .max_by_key(|(row, col)| survey.viewing_score(*row, *col))
+}
+
+fn part2(puzzle: &[Vec<u8>]) -> usize {
+    let survey = survey(puzzle);
+    best_spot(puzzle, &survey).map_or(0, |(row, col)| survey.viewing_score(row, col))
+}
+
+/// How each tree is drawn in the heat map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mark {
+    Hidden,
+    Visible,
+    SightLine,
+    Best,
+}
+
+fn marks(puzzle: &[Vec<u8>], survey: &Survey) -> Vec<Vec<Mark>> {
+    let mut marks: Vec<Vec<Mark>> = survey
+        .visible
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|v| if *v { Mark::Visible } else { Mark::Hidden })
+                .collect()
+        })
+        .collect();
+    if let Some((row, col)) = best_spot(puzzle, survey) {
+        for dir in Direction::ALL {
+            for d in 1..=survey.viewing_distance(row, col, dir) {
+                let (r, c) = dir.step(row, col, d).unwrap();
+                marks[r][c] = Mark::SightLine;
+            }
+        }
+        marks[row][col] = Mark::Best;
+    }
+    marks
+}
+
+/// Background colour for a tree: shaded by height, tinted by its mark.
+fn shade(height: u8, mark: Mark) -> [u8; 3] {
+    let base = elevation(height, 9);
+    let tint =
+        |rgb: [u8; 3]| [0, 1, 2].map(|i| ((u16::from(base[i]) + u16::from(rgb[i])) / 2) as u8);
+    match mark {
+        Mark::Hidden => base.map(|v| v / 2),
+        Mark::Visible => base,
+        Mark::SightLine => tint([40, 120, 255]),
+        Mark::Best => [220, 30, 30],
+    }
+}
+
+fn render_ansi(puzzle: &[Vec<u8>], survey: &Survey) -> String {
+    let mut out = String::new();
+    for (row, marks) in puzzle.iter().zip(marks(puzzle, survey)) {
+        for (height, mark) in row.iter().zip(marks) {
+            let fg = match mark {
+                Mark::Hidden => [120, 120, 120],
+                Mark::Visible => [255, 230, 60],
+                Mark::SightLine | Mark::Best => [255, 255, 255],
+            };
+            out += &ansi_bg(shade(*height, mark));
+            out += &ansi_fg(fg);
+            out += &height.to_string();
+        }
+        out += ANSI_RESET;
+        out.push('\n');
+    }
+    out
+}
+
+fn render_image(puzzle: &[Vec<u8>], survey: &Survey, scale: usize) -> Image {
+    let cols = puzzle.first().map_or(0, |r| r.len());
+    let mut image = Image::new(cols * scale, puzzle.len() * scale);
+    for (r, (row, marks)) in puzzle.iter().zip(marks(puzzle, survey)).enumerate() {
+        for (c, (height, mark)) in row.iter().zip(marks).enumerate() {
+            image.fill_cell(r, c, scale, shade(*height, mark));
+        }
+    }
+    image
+}
+
+/// Parses a stack of forests separated by blank lines as a 3D volume. A
+/// single forest stays two-dimensional.
+#[derive(Debug, Error, PartialEq, Eq)]
+enum ShapeError {
+    #[error("layer {layer} has {found} rows, expected {expected}")]
+    Rows {
+        layer: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("layer {layer}, row {row} has {found} trees, expected {expected}")]
+    Columns {
+        layer: usize,
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+fn parse_volume(input: &str) -> Result<Volume, ShapeError> {
+    let input = input.replace("\r\n", "\n");
+    let layers: Vec<Vec<Vec<u8>>> = input.trim_end().split("\n\n").map(parse).collect();
+    let grid = Volume::from_grid(&layers[0]);
+    let (rows, cols) = (grid.shape[0], grid.shape[1]);
+    for (l, layer) in layers.iter().enumerate() {
+        if layer.len() != rows {
+            return Err(ShapeError::Rows {
+                layer: l + 1,
+                expected: rows,
+                found: layer.len(),
+            });
+        }
+        if let Some((r, row)) = layer.iter().enumerate().find(|(_, row)| row.len() != cols) {
+            return Err(ShapeError::Columns {
+                layer: l + 1,
+                row: r + 1,
+                expected: cols,
+                found: row.len(),
+            });
+        }
+    }
+    if layers.len() == 1 {
+        return Ok(grid);
+    }
+    let mut shape = vec![layers.len()];
+    shape.extend(grid.shape);
+    Ok(Volume {
+        shape,
+        heights: layers.iter().flatten().flatten().copied().collect(),
+    })
+}
+
+fn part1_volume(volume: &Volume) -> usize {
+    volume.points().filter(|p| volume.is_visible(p)).count()
+}
+
+fn part2_volume(volume: &Volume) -> usize {
+    volume
+        .points()
+        .map(|p| volume.viewing_score(&p))
+        .max()
+        .unwrap_or(0)
+}
+
+fn part1_naive(puzzle: &[Vec<u8>]) -> usize {
     let rows = puzzle.len();
     let cols = puzzle[0].len();
     let mut visible: usize = 0;
@@ -133,7 +452,7 @@ fn part1(puzzle: &Vec<Vec<u8>>) -> usize {
     visible
 }
 
-fn part2(puzzle: &Vec<Vec<u8>>) -> usize {
+fn part2_naive(puzzle: &[Vec<u8>]) -> usize {
     let rows = puzzle.len();
     let cols = puzzle[0].len();
     let mut best: usize = 0;
@@ -148,21 +467,50 @@ fn part2(puzzle: &Vec<Vec<u8>>) -> usize {
     best
 }
 
-fn main() {
+fn main() -> Result<()> {
     let input = include_str!("../../input/08.txt");
+    if std::env::args().any(|a| a == "--volume") {
+        let volume = parse_volume(input)?;
+        println!("Part 1: {:?}", part1_volume(&volume));
+        println!("Part 2: {:?}", part2_volume(&volume));
+        return Ok(());
+    }
+
     let parsed = parse(input);
+    let args: Vec<String> = std::env::args().collect();
+    let naive = args.iter().any(|a| a == "--naive");
 
     let timer = Instant::now();
-    let p1 = part1(&parsed);
+    let p1 = if naive {
+        part1_naive(&parsed)
+    } else {
+        part1(&parsed)
+    };
     println!("Part 1: {:?}\n(elapsed: {:.2?})", p1, timer.elapsed());
 
     let timer = Instant::now();
-    let p2 = part2(&parsed);
+    let p2 = if naive {
+        part2_naive(&parsed)
+    } else {
+        part2(&parsed)
+    };
     println!("Part 2: {:?}\n(elapsed: {:.2?})", p2, timer.elapsed());
+
+    let survey = survey(&parsed);
+    if args.iter().any(|a| a == "--render") {
+        print!("{}", render_ansi(&parsed, &survey));
+    }
+    if let Some(file) = args.iter().skip_while(|a| *a != "--ppm").nth(1) {
+        render_image(&parsed, &survey, 8).save_ppm(file)?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use super::Direction;
+    use proptest::prelude::*;
+
     const SAMPLE: &str = r"30373
 25512
 65332
@@ -180,4 +528,132 @@ mod tests {
         let input = super::parse(SAMPLE);
         assert_eq!(8, super::part2(&input));
     }
+
+    #[test]
+    fn naive_works() {
+        let input = super::parse(SAMPLE);
+        assert_eq!(21, super::part1_naive(&input));
+        assert_eq!(8, super::part2_naive(&input));
+    }
+
+    #[test]
+    fn viewing_distance_works() {
+        let input = super::parse(SAMPLE);
+        // The puzzle's example: the 5 in the middle of the fourth row.
+        assert_eq!(2, super::viewing_distance(&input, 3, 2, Direction::North));
+        assert_eq!(2, super::viewing_distance(&input, 3, 2, Direction::West));
+        assert_eq!(1, super::viewing_distance(&input, 3, 2, Direction::South));
+        assert_eq!(2, super::viewing_distance(&input, 3, 2, Direction::East));
+        let survey = super::survey(&input);
+        assert_eq!(2, survey.viewing_distance(3, 2, Direction::North));
+        assert_eq!(1, survey.viewing_distance(3, 2, Direction::South));
+    }
+
+    #[test]
+    fn marks_work() {
+        use super::Mark::*;
+        let input = super::parse(SAMPLE);
+        let survey = super::survey(&input);
+        assert_eq!(Some((3, 2)), super::best_spot(&input, &survey));
+        let marks = super::marks(&input, &survey);
+        assert_eq!(
+            vec![SightLine, SightLine, Best, SightLine, SightLine],
+            marks[3]
+        );
+        assert_eq!(Hidden, marks[1][3]);
+        assert_eq!(Visible, marks[2][3]);
+        assert_eq!(SightLine, marks[1][2]);
+    }
+
+    #[test]
+    fn render_image_works() {
+        let input = super::parse(SAMPLE);
+        let survey = super::survey(&input);
+        let image = super::render_image(&input, &survey, 3);
+        assert_eq!((15, 15), (image.width(), image.height()));
+        assert_eq!([220, 30, 30], image.get(7, 10));
+    }
+
+    #[test]
+    fn volume_matches_grid() {
+        let volume = super::parse_volume(SAMPLE).unwrap();
+        assert_eq!(21, super::part1_volume(&volume));
+        assert_eq!(8, super::part2_volume(&volume));
+    }
+
+    #[test]
+    fn volume_3d_works() {
+        let volume =
+            super::parse_volume("111\n111\n111\n\n111\n101\n111\n\n111\n111\n111").unwrap();
+        assert_eq!(vec![3, 3, 3], volume.shape);
+        // Only the centre is hidden, and it is the only tree that sees in
+        // all six directions.
+        assert_eq!(26, super::part1_volume(&volume));
+        assert_eq!(1, super::part2_volume(&volume));
+        assert!(!volume.is_visible(&[1, 1, 1]));
+    }
+
+    #[test]
+    fn volume_shapes_are_checked() {
+        use super::ShapeError;
+        let crlf = super::parse_volume("111\r\n111\r\n\r\n111\r\n101\r\n").unwrap();
+        assert_eq!(vec![2, 2, 3], crlf.shape);
+        assert_eq!(
+            Err(ShapeError::Rows {
+                layer: 2,
+                expected: 2,
+                found: 3
+            }),
+            super::parse_volume("111\n111\n\n111\n111\n111").map(|v| v.shape)
+        );
+        assert_eq!(
+            Err(ShapeError::Columns {
+                layer: 2,
+                row: 2,
+                expected: 3,
+                found: 2
+            }),
+            super::parse_volume("111\n111\n\n111\n11").map(|v| v.shape)
+        );
+        assert_eq!(
+            Err(ShapeError::Columns {
+                layer: 1,
+                row: 2,
+                expected: 3,
+                found: 4
+            }),
+            super::parse_volume("111\n1111").map(|v| v.shape)
+        );
+    }
+
+    fn forest() -> impl Strategy<Value = Vec<Vec<u8>>> {
+        (1..12usize, 1..12usize).prop_flat_map(|(rows, cols)| {
+            prop::collection::vec(prop::collection::vec(0..10u8, cols), rows)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn survey_matches_naive(puzzle in forest()) {
+            let survey = super::survey(&puzzle);
+            for row in 0..puzzle.len() {
+                for col in 0..puzzle[0].len() {
+                    prop_assert_eq!(
+                        super::is_visible(&puzzle, row, col),
+                        survey.visible[row][col]
+                    );
+                    for dir in Direction::ALL {
+                        prop_assert_eq!(
+                            super::viewing_distance(&puzzle, row, col, dir),
+                            survey.viewing_distance(row, col, dir)
+                        );
+                    }
+                    prop_assert_eq!(
+                        super::viewing_score(&puzzle, row, col),
+                        survey.viewing_score(row, col)
+                    );
+                }
+            }
+        }
+    }
 }
@@ -14,149 +14,291 @@
 
 #![warn(clippy::all)]
 
-use std::{collections::HashSet, time::Instant};
+use anyhow::{anyhow, Result};
+use aoc22::render::Image;
+use std::{
+    collections::HashSet,
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
 
-enum Step {
-    Left(u8),
-    Right(u8),
-    Up(u8),
-    Down(u8),
+/// Moves the head by `delta`, `count` times over. Compass steps have a
+/// single-square `delta`, but any vector is allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Step {
+    delta: Point,
+    count: u32,
 }
 
 #[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
 struct Point(i32, i32);
 
+/// Moves `tail` one square towards `head` (diagonally if they share neither
+/// row nor column), unless it is already touching it.
 fn follow_head(head: Point, tail: Point) -> Point {
-    let mut result = tail;
-    let mut moved_x = false;
-    let mut moved_y = false;
-    if head.0 - 1 > tail.0 {
-        result.0 = head.0 - 1;
-        moved_x = true;
-    } else if head.0 + 1 < tail.0 {
-        result.0 = head.0 + 1;
-        moved_x = true;
-    }
-    if head.1 - 1 > tail.1 {
-        result.1 = head.1 - 1;
-        moved_y = true;
-    } else if head.1 + 1 < tail.1 {
-        result.1 = head.1 + 1;
-        moved_y = true;
-    }
-    if moved_x && !moved_y {
-        result.1 = head.1;
-    } else if moved_y && !moved_x {
-        result.0 = head.0;
-    }
-    result
-}
-
-fn parse(input: &str) -> Vec<Step> {
-    let mut steps: Vec<Step> = Vec::new();
-    for line in input.lines() {
-        let mut parts = line.split_whitespace();
-        let direction = parts.next().unwrap().chars().next().unwrap();
-        let distance = parts.next().unwrap().parse().unwrap();
-        steps.push(match direction {
-            'L' => Step::Left(distance),
-            'R' => Step::Right(distance),
-            'U' => Step::Up(distance),
-            'D' => Step::Down(distance),
-            _ => unreachable!(),
-        })
+    let dx = head.0 - tail.0;
+    let dy = head.1 - tail.1;
+    if dx.abs() <= 1 && dy.abs() <= 1 {
+        tail
+    } else {
+        Point(tail.0 + dx.signum(), tail.1 + dy.signum())
     }
-    steps
 }
 
-fn part1(steps: &[Step]) -> usize {
-    let mut head = Point(0, 0);
-    let mut tail = Point(0, 0);
-    let mut visited = HashSet::new();
-    for step in steps {
-        match step {
-            Step::Left(distance) => {
-                for _ in 0..*distance {
-                    head = Point(head.0 - 1, head.1);
-                    tail = follow_head(head, tail);
-                    visited.insert(tail);
-                }
-            }
-            Step::Right(distance) => {
-                for _ in 0..*distance {
-                    head = Point(head.0 + 1, head.1);
-                    tail = follow_head(head, tail);
-                    visited.insert(tail);
-                }
-            }
-            Step::Up(distance) => {
-                for _ in 0..*distance {
-                    head = Point(head.0, head.1 + 1);
-                    tail = follow_head(head, tail);
-                    visited.insert(tail);
+fn parse_direction(direction: &str) -> Option<Point> {
+    Some(match direction {
+        "L" => Point(-1, 0),
+        "R" => Point(1, 0),
+        "U" => Point(0, 1),
+        "D" => Point(0, -1),
+        "UL" => Point(-1, 1),
+        "UR" => Point(1, 1),
+        "DL" => Point(-1, -1),
+        "DR" => Point(1, -1),
+        _ => return None,
+    })
+}
+
+/// Parses a step, either a compass direction and distance (`R 4`, `UL 3`)
+/// or a vector and an optional repeat count (`3,-2` or `3,-2 4`).
+fn parse_step(line: &str) -> Result<Step> {
+    let mut parts = line.split_whitespace();
+    let first = parts.next().ok_or_else(|| anyhow!("empty step"))?;
+    let second = parts.next();
+    if parts.next().is_some() {
+        return Err(anyhow!("invalid step: {}", line));
+    }
+    if let Some((x, y)) = first.split_once(',') {
+        return Ok(Step {
+            delta: Point(x.parse()?, y.parse()?),
+            count: second.map_or(Ok(1), str::parse)?,
+        });
+    }
+    Ok(Step {
+        delta: parse_direction(first).ok_or_else(|| anyhow!("invalid direction: {}", line))?,
+        count: second
+            .ok_or_else(|| anyhow!("missing distance: {}", line))?
+            .parse()?,
+    })
+}
+
+fn parse(input: &str) -> Result<Vec<Step>> {
+    input.lines().map(parse_step).collect()
+}
+
+/// A rope of any number of knots, tracking every position each knot visits.
+struct Rope {
+    knots: Vec<Point>,
+    visited: Vec<HashSet<Point>>,
+}
+
+impl Rope {
+    fn new(knot_count: usize) -> Self {
+        assert!(knot_count > 0, "a rope needs at least one knot");
+        Rope {
+            knots: vec![Point(0, 0); knot_count],
+            visited: vec![HashSet::from([Point(0, 0)]); knot_count],
+        }
+    }
+
+    fn apply(&mut self, step: &Step) {
+        self.apply_with(step, |_| Ok(())).unwrap();
+    }
+
+    /// Like `apply`, but calls `on_move` with the rope after each head move.
+    fn apply_with<F>(&mut self, step: &Step, mut on_move: F) -> Result<()>
+    where
+        F: FnMut(&Rope) -> Result<()>,
+    {
+        for _ in 0..step.count {
+            self.knots[0] = Point(
+                self.knots[0].0 + step.delta.0,
+                self.knots[0].1 + step.delta.1,
+            );
+            self.visited[0].insert(self.knots[0]);
+            self.settle();
+            on_move(self)?;
+        }
+        Ok(())
+    }
+
+    /// Lets the knots catch up one square at a time until the rope is taut.
+    /// After a single-square head move this takes one pass, but a long jump
+    /// drags the rest of the rope through every square in between.
+    fn settle(&mut self) {
+        loop {
+            let mut moved = false;
+            for k in 1..self.knots.len() {
+                let next = follow_head(self.knots[k - 1], self.knots[k]);
+                if next != self.knots[k] {
+                    self.knots[k] = next;
+                    self.visited[k].insert(next);
+                    moved = true;
                 }
             }
-            Step::Down(distance) => {
-                for _ in 0..*distance {
-                    head = Point(head.0, head.1 - 1);
-                    tail = follow_head(head, tail);
-                    visited.insert(tail);
-                }
+            if !moved {
+                break;
             }
         }
     }
-    visited.len()
+
+    fn tail(&self) -> usize {
+        self.knots.len() - 1
+    }
+
+    /// Every position knot `k` has occupied (0 is the head).
+    fn visited(&self, k: usize) -> &HashSet<Point> {
+        &self.visited[k]
+    }
 }
 
-fn part2(steps: &[Step]) -> usize {
-    let mut knots = [Point(0, 0); 10];
-    let mut visited = HashSet::new();
+fn simulate(steps: &[Step], knot_count: usize) -> Rope {
+    let mut rope = Rope::new(knot_count);
     for step in steps {
-        match step {
-            Step::Left(distance) => {
-                for _ in 0..*distance {
-                    knots[0] = Point(knots[0].0 - 1, knots[0].1);
-                    for k in 1..=9 {
-                        knots[k] = follow_head(knots[k - 1], knots[k]);
-                    }
-                    visited.insert(knots[9]);
-                }
-            }
-            Step::Right(distance) => {
-                for _ in 0..*distance {
-                    knots[0] = Point(knots[0].0 + 1, knots[0].1);
-                    for k in 1..=9 {
-                        knots[k] = follow_head(knots[k - 1], knots[k]);
-                    }
-                    visited.insert(knots[9]);
-                }
-            }
-            Step::Up(distance) => {
-                for _ in 0..*distance {
-                    knots[0] = Point(knots[0].0, knots[0].1 + 1);
-                    for k in 1..=9 {
-                        knots[k] = follow_head(knots[k - 1], knots[k]);
-                    }
-                    visited.insert(knots[9]);
-                }
-            }
-            Step::Down(distance) => {
-                for _ in 0..*distance {
-                    knots[0] = Point(knots[0].0, knots[0].1 - 1);
-                    for k in 1..=9 {
-                        knots[k] = follow_head(knots[k - 1], knots[k]);
-                    }
-                    visited.insert(knots[9]);
-                }
-            }
+        rope.apply(step);
+    }
+    rope
+}
+
+/// The area covered by the head's path, which contains every other knot too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Bounds {
+    min: Point,
+    max: Point,
+}
+
+impl Bounds {
+    fn of(steps: &[Step]) -> Self {
+        let mut head = Point(0, 0);
+        let mut bounds = Bounds {
+            min: head,
+            max: head,
+        };
+        for step in steps {
+            let count = step.count as i32;
+            head = Point(head.0 + step.delta.0 * count, head.1 + step.delta.1 * count);
+            bounds.min = Point(bounds.min.0.min(head.0), bounds.min.1.min(head.1));
+            bounds.max = Point(bounds.max.0.max(head.0), bounds.max.1.max(head.1));
+        }
+        bounds
+    }
+
+    fn width(&self) -> usize {
+        (self.max.0 - self.min.0 + 1) as usize
+    }
+
+    fn height(&self) -> usize {
+        (self.max.1 - self.min.1 + 1) as usize
+    }
+
+    /// All points, top row first, as (column, row, point) in image order.
+    fn cells(&self) -> impl Iterator<Item = (usize, usize, Point)> + '_ {
+        (self.min.1..=self.max.1)
+            .rev()
+            .enumerate()
+            .flat_map(move |(r, y)| {
+                (self.min.0..=self.max.0)
+                    .enumerate()
+                    .map(move |(c, x)| (c, r, Point(x, y)))
+            })
+    }
+}
+
+/// What the puzzle would draw at `p`: the front-most knot (`H`, then `1`..`9`,
+/// or `T` for a two-knot rope), else `s` for the start, `#` for the tail's
+/// trail, or `.`.
+fn cell(rope: &Rope, p: Point) -> char {
+    if let Some(k) = rope.knots.iter().position(|knot| *knot == p) {
+        match k {
+            0 => 'H',
+            1 if rope.knots.len() == 2 => 'T',
+            _ => char::from_digit(k as u32 % 10, 10).unwrap(),
         }
+    } else if p == Point(0, 0) {
+        's'
+    } else if rope.visited(rope.tail()).contains(&p) {
+        '#'
+    } else {
+        '.'
+    }
+}
+
+fn render_frame(rope: &Rope, bounds: &Bounds) -> String {
+    let mut out = String::new();
+    for (c, _, p) in bounds.cells() {
+        out.push(cell(rope, p));
+        if c + 1 == bounds.width() {
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn render_image(rope: &Rope, bounds: &Bounds, scale: usize) -> Image {
+    let mut image = Image::new(bounds.width() * scale, bounds.height() * scale);
+    for (c, r, p) in bounds.cells() {
+        let rgb = match cell(rope, p) {
+            'H' => [220, 30, 30],
+            '.' => [20, 20, 30],
+            '#' => [110, 110, 130],
+            's' => [40, 180, 60],
+            _ => [250, 160, 40],
+        };
+        image.fill_cell(r, c, scale, rgb);
     }
-    visited.len()
+    image
+}
+
+/// Replays `steps` on a fresh rope, calling `on_frame` after every head move.
+fn animate<F>(steps: &[Step], knot_count: usize, mut on_frame: F) -> Result<()>
+where
+    F: FnMut(&Rope) -> Result<()>,
+{
+    let mut rope = Rope::new(knot_count);
+    on_frame(&rope)?;
+    for step in steps {
+        rope.apply_with(step, &mut on_frame)?;
+    }
+    Ok(())
+}
+
+fn play(steps: &[Step], knot_count: usize, delay: Duration) -> Result<()> {
+    let bounds = Bounds::of(steps);
+    animate(steps, knot_count, |rope| {
+        print!("\x1b[2J\x1b[H{}", render_frame(rope, &bounds));
+        thread::sleep(delay);
+        Ok(())
+    })
 }
 
-fn main() {
+/// Writes one numbered PPM image per frame into `dir`.
+fn export_frames(steps: &[Step], knot_count: usize, dir: &Path, scale: usize) -> Result<usize> {
+    fs::create_dir_all(dir)?;
+    let bounds = Bounds::of(steps);
+    let mut frame = 0;
+    animate(steps, knot_count, |rope| {
+        render_image(rope, &bounds, scale).save_ppm(dir.join(format!("frame_{:05}.ppm", frame)))?;
+        frame += 1;
+        Ok(())
+    })?;
+    Ok(frame)
+}
+
+fn part1(steps: &[Step]) -> usize {
+    let rope = simulate(steps, 2);
+    rope.visited(rope.tail()).len()
+}
+
+fn part2(steps: &[Step]) -> usize {
+    let rope = simulate(steps, 10);
+    rope.visited(rope.tail()).len()
+}
+
+fn main() -> Result<()> {
     let input = include_str!("../../input/09.txt");
-    let parsed = parse(input);
+    let parsed = parse(input)?;
 
     let timer = Instant::now();
     let p1 = part1(&parsed);
@@ -165,10 +307,28 @@ fn main() {
     let timer = Instant::now();
     let p2 = part2(&parsed);
     println!("Part 2: {:?}\n(elapsed: {:.2?})", p2, timer.elapsed());
+
+    let args: Vec<String> = std::env::args().collect();
+    let knots = if args.iter().any(|a| a == "--short") {
+        2
+    } else {
+        10
+    };
+    if args.iter().any(|a| a == "--play") {
+        play(&parsed, knots, Duration::from_millis(50))?;
+    }
+    if let Some(dir) = args.iter().skip_while(|a| *a != "--frames").nth(1) {
+        let count = export_frames(&parsed, knots, Path::new(dir), 4)?;
+        println!("Wrote {} frames to {}", count, dir);
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{Point, Step};
+
     const SAMPLE: &str = r"R 4
 U 4
 L 3
@@ -189,13 +349,116 @@ U 20";
 
     #[test]
     fn part1_works() {
-        let input = super::parse(SAMPLE);
+        let input = super::parse(SAMPLE).unwrap();
         assert_eq!(13, super::part1(&input));
     }
 
     #[test]
     fn part2_works() {
-        let input = super::parse(SAMPLE2);
+        let input = super::parse(SAMPLE2).unwrap();
         assert_eq!(36, super::part2(&input));
     }
+
+    #[test]
+    fn rope_knots_work() {
+        let input = super::parse(SAMPLE).unwrap();
+        let rope = super::simulate(&input, 10);
+        assert_eq!(13, rope.visited(1).len());
+        assert_eq!(1, rope.visited(9).len());
+        assert_eq!(super::Point(2, 2), rope.knots[0]);
+    }
+
+    #[test]
+    fn render_frame_works() {
+        let input = super::parse(SAMPLE).unwrap();
+        let bounds = super::Bounds::of(&input);
+        let mut frames = vec![];
+        super::animate(&input, 2, |rope| {
+            frames.push(super::render_frame(rope, &bounds));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(25, frames.len());
+        assert_eq!("......\n......\n......\n......\nH.....\n", frames[0]);
+        assert_eq!("......\n......\n......\n......\ns##TH.\n", frames[4]);
+        assert_eq!("..##..\n...##.\n.TH##.\n....#.\ns###..\n", frames[24]);
+    }
+
+    #[test]
+    fn render_long_rope_works() {
+        let input = super::parse(SAMPLE).unwrap();
+        let bounds = super::Bounds::of(&input);
+        let mut last = String::new();
+        super::animate(&input, 10, |rope| {
+            last = super::render_frame(rope, &bounds);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!("......\n......\n.1H3..\n.5....\n6.....\n", last);
+    }
+
+    #[test]
+    fn export_frames_works() {
+        let input = super::parse(SAMPLE).unwrap();
+        let dir = std::env::temp_dir().join(format!("day09-frames-{}", std::process::id()));
+        assert_eq!(25, super::export_frames(&input, 2, &dir, 2).unwrap());
+        let first = std::fs::read(dir.join("frame_00000.ppm")).unwrap();
+        assert!(first.starts_with(b"P6\n12 10\n255\n"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_step_works() {
+        assert_eq!(
+            Step {
+                delta: Point(-1, 1),
+                count: 300
+            },
+            super::parse_step("UL 300").unwrap()
+        );
+        assert_eq!(
+            Step {
+                delta: Point(3, -2),
+                count: 1
+            },
+            super::parse_step("3,-2").unwrap()
+        );
+        assert_eq!(
+            Step {
+                delta: Point(3, -2),
+                count: 4
+            },
+            super::parse_step("3,-2 4").unwrap()
+        );
+        assert!(super::parse_step("X 1").is_err());
+        assert!(super::parse_step("R").is_err());
+    }
+
+    #[test]
+    fn follow_diagonal_head_works() {
+        // The head can end up two squares away on both axes.
+        assert_eq!(Point(1, 1), super::follow_head(Point(2, 2), Point(0, 0)));
+        assert_eq!(Point(1, -1), super::follow_head(Point(2, -2), Point(0, 0)));
+        assert_eq!(Point(1, 1), super::follow_head(Point(1, 2), Point(0, 0)));
+        assert_eq!(Point(0, 0), super::follow_head(Point(1, 1), Point(0, 0)));
+    }
+
+    #[test]
+    fn diagonal_steps_work() {
+        let steps = super::parse("UR 4\nDL 2").unwrap();
+        let rope = super::simulate(&steps, 3);
+        // The whole rope trails along the diagonal.
+        assert_eq!(4, rope.visited(1).len());
+        assert_eq!(3, rope.visited(2).len());
+        assert_eq!(Point(2, 2), rope.knots[0]);
+    }
+
+    #[test]
+    fn vector_jump_drags_rope() {
+        let steps = super::parse("5,1").unwrap();
+        let rope = super::simulate(&steps, 2);
+        // The tail is dragged through every square up to the head.
+        assert_eq!(Point(4, 1), rope.knots[1]);
+        assert_eq!(5, rope.visited(1).len());
+    }
 }
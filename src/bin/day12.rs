@@ -16,14 +16,18 @@
 
 use std::time::Instant;
 
+use anyhow::Result;
+use aoc22::render::{ansi_bg, ansi_fg, elevation, Image, ANSI_RESET};
 use array2d::Array2D;
-use pathfinding::prelude::bfs;
+use pathfinding::prelude::{bfs, dijkstra};
 
 // These are derived from the known actual input, plus a border wall.
 const MAX_ROWS: usize = 43;
 const MAX_COLS: usize = 164;
 
 struct Map {
+    rows: usize,
+    cols: usize,
     start: (usize, usize),
     end: (usize, usize),
     heights: Array2D<u8>,
@@ -31,12 +35,16 @@ struct Map {
 
 fn parse(input: &str) -> Map {
     let mut out = Map {
+        rows: 0,
+        cols: 0,
         start: (0, 0),
         end: (0, 0),
         heights: Array2D::filled_with(u8::MAX, MAX_ROWS, MAX_COLS),
     };
     for (r, line) in input.lines().enumerate() {
         let row = r + 1; // Leave a border wall.
+        out.rows = out.rows.max(r + 1);
+        out.cols = out.cols.max(line.len());
         for (c, ch) in line.bytes().enumerate() {
             let col = c + 1; // Leave a border wall.
             match ch {
@@ -56,41 +64,196 @@ fn parse(input: &str) -> Map {
     out
 }
 
-fn can_travel(from: u8, to: u8) -> bool {
-    to <= from + 1
+/// Height used for the border wall; never enterable, whatever the rule.
+const WALL: u8 = u8::MAX;
+
+const ORTHOGONAL: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const DIAGONAL: [(isize, isize); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+
+/// Movement constraints for a single step between neighbouring squares.
+trait ClimbRule {
+    /// Maximum height gained in one step.
+    fn max_ascent(&self) -> u8;
+
+    /// Maximum height lost in one step.
+    fn max_descent(&self) -> u8;
+
+    /// Whether diagonal neighbours are reachable.
+    fn diagonal(&self) -> bool {
+        false
+    }
+
+    fn can_travel(&self, from: u8, to: u8) -> bool {
+        if to == WALL {
+            false
+        } else if to >= from {
+            to - from <= self.max_ascent()
+        } else {
+            from - to <= self.max_descent()
+        }
+    }
+}
+
+/// The puzzle's rule: up at most one, down any amount.
+struct PuzzleRule;
+
+impl ClimbRule for PuzzleRule {
+    fn max_ascent(&self) -> u8 {
+        1
+    }
+
+    fn max_descent(&self) -> u8 {
+        u8::MAX
+    }
 }
 
-fn reachable(map: &Map, current: &(usize, usize)) -> Vec<(usize, usize)> {
-    let mut out = vec![];
+/// Arbitrary limits, for exploring route-planning variants.
+struct Limits {
+    ascent: u8,
+    descent: u8,
+    diagonal: bool,
+}
+
+impl ClimbRule for Limits {
+    fn max_ascent(&self) -> u8 {
+        self.ascent
+    }
+
+    fn max_descent(&self) -> u8 {
+        self.descent
+    }
+
+    fn diagonal(&self) -> bool {
+        self.diagonal
+    }
+}
+
+/// Cost of a single step, given the heights it goes from and to.
+type Cost = fn(u8, u8) -> usize;
+
+fn height_change_cost(from: u8, to: u8) -> usize {
+    1 + from.abs_diff(to) as usize
+}
+
+fn reachable(map: &Map, rule: &dyn ClimbRule, current: &(usize, usize)) -> Vec<(usize, usize)> {
     let (row, col) = *current;
-    if can_travel(map.heights[(row, col)], map.heights[(row - 1, col)]) {
-        out.push((row - 1, col));
+    let from = map.heights[(row, col)];
+    let diagonal: &[(isize, isize)] = if rule.diagonal() { &DIAGONAL } else { &[] };
+    // The border wall guarantees that every neighbour is in bounds.
+    ORTHOGONAL
+        .iter()
+        .chain(diagonal)
+        .map(|(dr, dc)| (row.wrapping_add_signed(*dr), col.wrapping_add_signed(*dc)))
+        .filter(|p| rule.can_travel(from, map.heights[*p]))
+        .collect()
+}
+
+/// Finds the cheapest route from `start` to the map's end, returning the path
+/// and its cost. Without a cost function every step costs 1 and a plain BFS
+/// is used; otherwise the search switches to Dijkstra.
+fn shortest_path(
+    map: &Map,
+    start: (usize, usize),
+    rule: &dyn ClimbRule,
+    cost: Option<Cost>,
+) -> Option<(Vec<(usize, usize)>, usize)> {
+    match cost {
+        None => bfs(&start, |p| reachable(map, rule, p), |p| *p == map.end).map(|path| {
+            let steps = path.len() - 1;
+            (path, steps)
+        }),
+        Some(cost) => dijkstra(
+            &start,
+            |p| {
+                let from = map.heights[*p];
+                reachable(map, rule, p)
+                    .into_iter()
+                    .map(move |n| (n, cost(from, map.heights[n])))
+            },
+            |p| *p == map.end,
+        ),
     }
-    if can_travel(map.heights[(row, col)], map.heights[(row + 1, col)]) {
-        out.push((row + 1, col));
+}
+
+/// The route as the puzzle draws it: an arrow on each square showing the
+/// direction of the next step, `E` at the end and `.` everywhere else.
+/// Diagonal steps are drawn as `/` or `\\`.
+fn route_chars(map: &Map, path: &[(usize, usize)]) -> Vec<Vec<char>> {
+    let mut grid = vec![vec!['.'; map.cols]; map.rows];
+    for step in path.windows(2) {
+        let (from, to) = (step[0], step[1]);
+        let ch = match (
+            to.0 as isize - from.0 as isize,
+            to.1 as isize - from.1 as isize,
+        ) {
+            (0, 1) => '>',
+            (0, -1) => '<',
+            (1, 0) => 'v',
+            (-1, 0) => '^',
+            (1, 1) | (-1, -1) => '\\',
+            _ => '/',
+        };
+        grid[from.0 - 1][from.1 - 1] = ch;
     }
-    if can_travel(map.heights[(row, col)], map.heights[(row, col - 1)]) {
-        out.push((row, col - 1));
+    if let Some(last) = path.last() {
+        grid[last.0 - 1][last.1 - 1] = 'E';
     }
-    if can_travel(map.heights[(row, col)], map.heights[(row, col + 1)]) {
-        out.push((row, col + 1));
+    grid
+}
+
+fn render_text(map: &Map, path: &[(usize, usize)]) -> String {
+    route_chars(map, path)
+        .iter()
+        .map(|row| row.iter().collect::<String>() + "\n")
+        .collect()
+}
+
+/// Like `render_text`, but with each square shaded by its elevation.
+fn render_ansi(map: &Map, path: &[(usize, usize)]) -> String {
+    let mut out = String::new();
+    for (r, row) in route_chars(map, path).iter().enumerate() {
+        for (c, ch) in row.iter().enumerate() {
+            let height = map.heights[(r + 1, c + 1)];
+            let fg = if *ch == '.' {
+                [90, 90, 90]
+            } else {
+                [255, 40, 40]
+            };
+            out += &ansi_bg(elevation(height, 25));
+            out += &ansi_fg(fg);
+            out.push(*ch);
+        }
+        out += ANSI_RESET;
+        out.push('\n');
     }
     out
 }
 
-fn part1(map: &Map) -> usize {
-    let result = bfs(&map.start, |p| reachable(map, p), |p| *p == map.end).unwrap();
-    result.len() - 1
+fn render_image(map: &Map, path: &[(usize, usize)], scale: usize) -> Image {
+    let mut image = Image::new(map.cols * scale, map.rows * scale);
+    for r in 0..map.rows {
+        for c in 0..map.cols {
+            let height = map.heights[(r + 1, c + 1)];
+            image.fill_cell(r, c, scale, elevation(height, 25));
+        }
+    }
+    for (row, col) in path {
+        image.fill_cell(row - 1, col - 1, scale, [220, 30, 30]);
+    }
+    image
 }
 
-fn part2(map: &Map) -> usize {
+fn part1(map: &Map, rule: &dyn ClimbRule, cost: Option<Cost>) -> usize {
+    shortest_path(map, map.start, rule, cost).unwrap().1
+}
+
+fn part2(map: &Map, rule: &dyn ClimbRule, cost: Option<Cost>) -> usize {
     let mut best = usize::MAX;
     for r in 0..MAX_ROWS {
         for c in 0..MAX_COLS {
             if map.heights[(r, c)] == 0 {
-                let result = bfs(&(r, c), |p| reachable(map, p), |p| *p == map.end);
-                if let Some(path) = result {
-                    best = usize::min(best, path.len() - 1);
+                if let Some((_, total)) = shortest_path(map, (r, c), rule, cost) {
+                    best = usize::min(best, total);
                 }
             }
         }
@@ -98,21 +261,50 @@ fn part2(map: &Map) -> usize {
     best
 }
 
-fn main() {
+fn main() -> Result<()> {
     let input = include_str!("../../input/12.txt");
     let parsed = parse(input);
 
+    let args: Vec<String> = std::env::args().collect();
+    let rule: Box<dyn ClimbRule> = if args.iter().any(|a| a == "--diagonal") {
+        Box::new(Limits {
+            ascent: 1,
+            descent: u8::MAX,
+            diagonal: true,
+        })
+    } else {
+        Box::new(PuzzleRule)
+    };
+    let cost: Option<Cost> = if args.iter().any(|a| a == "--weighted") {
+        Some(height_change_cost)
+    } else {
+        None
+    };
+
     let timer = Instant::now();
-    let p1 = part1(&parsed);
+    let p1 = part1(&parsed, rule.as_ref(), cost);
     println!("Part 1: {:?}\n(elapsed: {:.2?})", p1, timer.elapsed());
 
     let timer = Instant::now();
-    let p2 = part2(&parsed);
+    let p2 = part2(&parsed, rule.as_ref(), cost);
     println!("Part 2: {:?}\n(elapsed: {:.2?})", p2, timer.elapsed());
+
+    let (path, _) = shortest_path(&parsed, parsed.start, rule.as_ref(), cost).unwrap();
+    if args.iter().any(|a| a == "--render") {
+        print!("{}", render_ansi(&parsed, &path));
+    } else if args.iter().any(|a| a == "--render-plain") {
+        print!("{}", render_text(&parsed, &path));
+    }
+    if let Some(file) = args.iter().skip_while(|a| *a != "--ppm").nth(1) {
+        render_image(&parsed, &path, 8).save_ppm(file)?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{height_change_cost, Limits, PuzzleRule};
+
     const SAMPLE: &str = r"Sabqponm
 abcryxxl
 accszExk
@@ -122,12 +314,90 @@ abdefghi";
     #[test]
     fn part1_works() {
         let input = super::parse(SAMPLE);
-        assert_eq!(31, super::part1(&input));
+        assert_eq!(31, super::part1(&input, &PuzzleRule, None));
     }
 
     #[test]
     fn part2_works() {
         let input = super::parse(SAMPLE);
-        assert_eq!(29, super::part2(&input));
+        assert_eq!(29, super::part2(&input, &PuzzleRule, None));
+    }
+
+    #[test]
+    fn diagonal_works() {
+        let input = super::parse(SAMPLE);
+        let rule = Limits {
+            ascent: 1,
+            descent: u8::MAX,
+            diagonal: true,
+        };
+        assert_eq!(27, super::part1(&input, &rule, None));
+    }
+
+    #[test]
+    fn weighted_works() {
+        let input = super::parse(SAMPLE);
+        let (path, cost) =
+            super::shortest_path(&input, input.start, &PuzzleRule, Some(height_change_cost))
+                .unwrap();
+        assert_eq!(input.end, *path.last().unwrap());
+        // Every route climbs at least 25 and takes at least 31 steps, and
+        // this one manages both.
+        assert_eq!(56, cost);
+
+        // The fewest steps dip from m to k and climb back; going round the
+        // dip on the m plateau below takes two more steps but is cheaper.
+        let input = super::parse(
+            "SbcdefghijklmklmnopqrstuvwxyE
+aaaaaaaaaaaammmmaaaaaaaaaaaaa",
+        );
+        let score = |path: &[(usize, usize)]| -> usize {
+            path.windows(2)
+                .map(|w| height_change_cost(input.heights[w[0]], input.heights[w[1]]))
+                .sum()
+        };
+        let (fewest, _) = super::shortest_path(&input, input.start, &PuzzleRule, None).unwrap();
+        let (cheapest, cost) =
+            super::shortest_path(&input, input.start, &PuzzleRule, Some(height_change_cost))
+                .unwrap();
+        assert_eq!((28, 57), (fewest.len() - 1, score(&fewest)));
+        assert_eq!((30, 55), (cheapest.len() - 1, cost));
+        assert_eq!(cost, score(&cheapest));
+    }
+
+    #[test]
+    fn render_text_works() {
+        let input = super::parse(SAMPLE);
+        let (path, _) = super::shortest_path(&input, input.start, &PuzzleRule, None).unwrap();
+        let text = super::render_text(&input, &path);
+        assert_eq!(
+            r"v..v<<<<
+>v.vv<<^
+.v.v>E^^
+.>v>>>^^
+..>>>>>^
+",
+            text
+        );
+    }
+
+    #[test]
+    fn render_image_works() {
+        let input = super::parse(SAMPLE);
+        let (path, _) = super::shortest_path(&input, input.start, &PuzzleRule, None).unwrap();
+        let image = super::render_image(&input, &path, 2);
+        assert_eq!((16, 10), (image.width(), image.height()));
+        assert_eq!([220, 30, 30], image.get(0, 0));
+    }
+
+    #[test]
+    fn limited_descent_works() {
+        let input = super::parse(SAMPLE);
+        let rule = Limits {
+            ascent: 1,
+            descent: 0,
+            diagonal: false,
+        };
+        assert_eq!(31, super::part1(&input, &rule, None));
     }
 }
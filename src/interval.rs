@@ -0,0 +1,258 @@
+//  Copyright 2022 Google LLC
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! Inclusive integer intervals and sets of them.
+
+use std::fmt;
+
+/// The integers `lo..=hi`. Never empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Interval {
+    lo: i64,
+    hi: i64,
+}
+
+impl Interval {
+    /// Panics if `lo > hi`; see `try_new` for the fallible version.
+    pub fn new(lo: i64, hi: i64) -> Self {
+        Self::try_new(lo, hi).expect("interval bounds are inverted")
+    }
+
+    pub fn try_new(lo: i64, hi: i64) -> Option<Self> {
+        (lo <= hi).then_some(Interval { lo, hi })
+    }
+
+    pub fn lo(&self) -> i64 {
+        self.lo
+    }
+
+    pub fn hi(&self) -> i64 {
+        self.hi
+    }
+
+    /// The number of integers covered.
+    pub fn len(&self) -> u64 {
+        self.hi.abs_diff(self.lo) + 1
+    }
+
+    /// Always false: an interval covers at least one integer.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn contains(&self, x: i64) -> bool {
+        self.lo <= x && x <= self.hi
+    }
+
+    /// Whether `other` lies entirely within `self`.
+    pub fn contains_interval(&self, other: &Interval) -> bool {
+        self.lo <= other.lo && other.hi <= self.hi
+    }
+
+    /// Whether the two share at least one integer.
+    pub fn overlaps(&self, other: &Interval) -> bool {
+        self.lo <= other.hi && other.lo <= self.hi
+    }
+
+    /// Whether the two overlap or sit next to each other with no gap, so
+    /// that their union is a single interval.
+    pub fn touches(&self, other: &Interval) -> bool {
+        self.lo <= other.hi.saturating_add(1) && other.lo <= self.hi.saturating_add(1)
+    }
+
+    pub fn intersection(&self, other: &Interval) -> Option<Interval> {
+        Interval::try_new(self.lo.max(other.lo), self.hi.min(other.hi))
+    }
+
+    /// The union, if it is a single interval.
+    pub fn union(&self, other: &Interval) -> Option<Interval> {
+        self.touches(other)
+            .then(|| Interval::new(self.lo.min(other.lo), self.hi.max(other.hi)))
+    }
+
+    /// What is left of `self` after removing `other`: zero, one or two pieces.
+    pub fn difference(&self, other: &Interval) -> IntervalSet {
+        let mut out = IntervalSet::new();
+        if !self.overlaps(other) {
+            out.insert(*self);
+            return out;
+        }
+        if self.lo < other.lo {
+            out.insert(Interval::new(self.lo, other.lo - 1));
+        }
+        if other.hi < self.hi {
+            out.insert(Interval::new(other.hi + 1, self.hi));
+        }
+        out
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.lo, self.hi)
+    }
+}
+
+/// A set of integers stored as sorted intervals, merged so that no two
+/// overlap or touch.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct IntervalSet {
+    intervals: Vec<Interval>,
+}
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        IntervalSet::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// The number of integers covered.
+    pub fn len(&self) -> u64 {
+        self.intervals.iter().map(Interval::len).sum()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Interval> {
+        self.intervals.iter()
+    }
+
+    pub fn contains(&self, x: i64) -> bool {
+        let i = self.intervals.partition_point(|iv| iv.hi < x);
+        self.intervals.get(i).is_some_and(|iv| iv.contains(x))
+    }
+
+    /// Adds an interval, merging it with any it overlaps or touches.
+    pub fn insert(&mut self, interval: Interval) {
+        let start = self
+            .intervals
+            .partition_point(|iv| iv.hi.saturating_add(1) < interval.lo);
+        let end = self
+            .intervals
+            .partition_point(|iv| iv.lo <= interval.hi.saturating_add(1));
+        let merged = self.intervals[start..end]
+            .iter()
+            .fold(interval, |acc, iv| acc.union(iv).unwrap());
+        self.intervals.splice(start..end, [merged]);
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        let mut out = self.clone();
+        for iv in &other.intervals {
+            out.insert(*iv);
+        }
+        out
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut out = IntervalSet::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let (a, b) = (self.intervals[i], other.intervals[j]);
+            if let Some(iv) = a.intersection(&b) {
+                out.intervals.push(iv);
+            }
+            if a.hi < b.hi {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        out
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        let mut out = IntervalSet::new();
+        for iv in &self.intervals {
+            let mut pieces = IntervalSet::from_iter([*iv]);
+            for cut in other.intervals.iter().filter(|c| c.overlaps(iv)) {
+                pieces = pieces
+                    .intervals
+                    .iter()
+                    .flat_map(|p| p.difference(cut).intervals)
+                    .collect();
+            }
+            out.intervals.extend(pieces.intervals);
+        }
+        out
+    }
+}
+
+impl FromIterator<Interval> for IntervalSet {
+    fn from_iter<I: IntoIterator<Item = Interval>>(iter: I) -> Self {
+        let mut out = IntervalSet::new();
+        for iv in iter {
+            out.insert(iv);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interval, IntervalSet};
+
+    fn set(intervals: &[(i64, i64)]) -> IntervalSet {
+        intervals
+            .iter()
+            .map(|(lo, hi)| Interval::new(*lo, *hi))
+            .collect()
+    }
+
+    #[test]
+    fn interval_works() {
+        let a = Interval::new(2, 8);
+        let b = Interval::new(3, 7);
+        let c = Interval::new(9, 10);
+        assert_eq!(None, Interval::try_new(5, 4));
+        assert_eq!(7, a.len());
+        assert!(a.contains_interval(&b) && !b.contains_interval(&a));
+        assert!(a.overlaps(&b) && !a.overlaps(&c));
+        assert!(a.touches(&c));
+        assert_eq!(Some(b), a.intersection(&b));
+        assert_eq!(None, a.intersection(&c));
+        assert_eq!(Some(Interval::new(2, 10)), a.union(&c));
+        assert_eq!(None, b.union(&c));
+        assert_eq!(set(&[(2, 2), (8, 8)]), a.difference(&b));
+        assert_eq!(set(&[]), b.difference(&a));
+        assert_eq!(set(&[(3, 7)]), b.difference(&c));
+        assert_eq!("2-8", a.to_string());
+    }
+
+    #[test]
+    fn insert_merges() {
+        let s = set(&[(10, 12), (1, 3), (5, 6), (4, 4), (20, 30), (25, 26)]);
+        assert_eq!(
+            vec![(1, 6), (10, 12), (20, 30)],
+            s.iter().map(|iv| (iv.lo(), iv.hi())).collect::<Vec<_>>()
+        );
+        assert_eq!(6 + 3 + 11, s.len());
+        assert!(s.contains(4) && s.contains(30) && !s.contains(7) && !s.contains(31));
+        let mut s = s;
+        s.insert(Interval::new(0, 100));
+        assert_eq!(set(&[(0, 100)]), s);
+    }
+
+    #[test]
+    fn set_operations_work() {
+        let a = set(&[(1, 5), (10, 15)]);
+        let b = set(&[(4, 11), (20, 21)]);
+        assert_eq!(set(&[(1, 15), (20, 21)]), a.union(&b));
+        assert_eq!(set(&[(4, 5), (10, 11)]), a.intersection(&b));
+        assert_eq!(set(&[(1, 3), (12, 15)]), a.difference(&b));
+        assert_eq!(set(&[(6, 9), (20, 21)]), b.difference(&a));
+        assert!(a.difference(&a).is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+//  Copyright 2022 Google LLC
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! Shared helpers for drawing puzzle state to the terminal and to image files.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+pub type Rgb = [u8; 3];
+
+pub const ANSI_RESET: &str = "\x1b[0m";
+
+/// Returns the escape sequence that sets the terminal background colour.
+pub fn ansi_bg(rgb: Rgb) -> String {
+    format!("\x1b[48;2;{};{};{}m", rgb[0], rgb[1], rgb[2])
+}
+
+/// Returns the escape sequence that sets the terminal foreground colour.
+pub fn ansi_fg(rgb: Rgb) -> String {
+    format!("\x1b[38;2;{};{};{}m", rgb[0], rgb[1], rgb[2])
+}
+
+/// Maps `value` in `0..=max` onto a green-to-brown-to-white elevation ramp.
+pub fn elevation(value: u8, max: u8) -> Rgb {
+    const STOPS: [Rgb; 3] = [[34, 110, 50], [140, 100, 60], [240, 240, 240]];
+    let t = if max == 0 {
+        0.0
+    } else {
+        f32::from(value.min(max)) / f32::from(max)
+    };
+    let (lo, hi, t) = if t < 0.5 {
+        (STOPS[0], STOPS[1], t * 2.0)
+    } else {
+        (STOPS[1], STOPS[2], (t - 0.5) * 2.0)
+    };
+    let mix = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * t).round() as u8;
+    [mix(lo[0], hi[0]), mix(lo[1], hi[1]), mix(lo[2], hi[2])]
+}
+
+/// A simple RGB raster that can be saved as a binary PPM (P6) image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgb>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![[0, 0, 0]; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Rgb {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Sets a pixel, silently ignoring coordinates outside the image.
+    pub fn set(&mut self, x: usize, y: usize, rgb: Rgb) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = rgb;
+        }
+    }
+
+    /// Fills the `scale`-sized square for grid cell (`row`, `col`).
+    pub fn fill_cell(&mut self, row: usize, col: usize, scale: usize, rgb: Rgb) {
+        for y in row * scale..(row + 1) * scale {
+            for x in col * scale..(col + 1) * scale {
+                self.set(x, y, rgb);
+            }
+        }
+    }
+
+    pub fn write_ppm<W: Write>(&self, mut out: W) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for px in &self.pixels {
+            out.write_all(px)?;
+        }
+        out.flush()
+    }
+
+    pub fn save_ppm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_ppm(BufWriter::new(File::create(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Image;
+
+    #[test]
+    fn ppm_header_and_pixels() {
+        let mut image = Image::new(2, 1);
+        image.set(1, 0, [1, 2, 3]);
+        image.set(5, 5, [9, 9, 9]);
+        let mut out = vec![];
+        image.write_ppm(&mut out).unwrap();
+        assert_eq!(b"P6\n2 1\n255\n\0\0\0\x01\x02\x03".to_vec(), out);
+    }
+
+    #[test]
+    fn elevation_endpoints() {
+        assert_eq!([34, 110, 50], super::elevation(0, 25));
+        assert_eq!([240, 240, 240], super::elevation(25, 25));
+    }
+}